@@ -0,0 +1,231 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use cookie_store::{Cookie, CookieStore};
+use reqwest_cookie_store::CookieStoreMutex;
+use tokio::sync::Mutex;
+use tracing::warn;
+
+use crate::error::{Result, TErrorKind};
+use crate::utils::write_atomic;
+
+const KEY_FILE: &str = "key";
+const KEYRING_SERVICE: &str = "fetcher2 cookies";
+const ACCOUNTS_DIR: &str = "accounts";
+const EXTENSION: &str = "cookies";
+const NONCE_LEN: usize = 24;
+
+// One encrypted file per cookie domain, all sharing one random key.
+// The key is kept in the keyring of the OS, or in a file in the same folder without one.
+// Every file is the nonce followed by the encrypted json of the cookies.
+// Saves are serialized, the modules of a session log in at the same time and each
+// save removes the files the others did not write.
+pub struct CookieJar {
+    dir: PathBuf,
+    cipher: XChaCha20Poly1305,
+    saving: Mutex<()>,
+}
+
+impl CookieJar {
+    pub fn open(dir: &Path) -> Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        let key = match keyring_key(dir) {
+            Ok(key) => key,
+            Err(err) => {
+                warn!(error = %err, "No keyring, the cookie key is saved next to the cookies");
+                file_key(dir)?
+            }
+        };
+        Ok(Self {
+            dir: dir.to_owned(),
+            cipher: XChaCha20Poly1305::new(&key),
+            saving: Mutex::new(()),
+        })
+    }
+
+    // Keeps the key in the folder, without touching the keyring
+    pub fn open_with_key_file(dir: &Path) -> Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        Ok(Self {
+            dir: dir.to_owned(),
+            cipher: XChaCha20Poly1305::new(&file_key(dir)?),
+            saving: Mutex::new(()),
+        })
+    }
+
+    // The cookies of another account, in a sub folder with the same key
+    pub fn account(&self, id: &str) -> Result<Self> {
+        let dir = self.dir.join(ACCOUNTS_DIR).join(id);
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self {
+            dir,
+            cipher: self.cipher.clone(),
+            saving: Mutex::new(()),
+        })
+    }
+
+    // Files that can't be read are skipped, that only costs a new login
+    pub fn load(&self) -> Result<CookieStore> {
+        let mut cookies = Vec::new();
+        for entry in std::fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some(EXTENSION) {
+                continue;
+            }
+            match self.read_file(&path) {
+                Ok(mut file_cookies) => cookies.append(&mut file_cookies),
+                Err(err) => warn!(?path, error = %err, "Could not load cookies"),
+            }
+        }
+        Ok(CookieStore::from_cookies(
+            cookies.into_iter().map(Ok::<_, TErrorKind>),
+            false,
+        )?)
+    }
+
+    pub async fn save(&self, store: &CookieStore) -> Result<()> {
+        let _saving = self.saving.lock().await;
+        self.write(store).await
+    }
+
+    // The snapshot is taken once it is this save's turn, so an older one never
+    // overwrites the cookies of a later save
+    pub async fn save_from(&self, cookies: &CookieStoreMutex) -> Result<()> {
+        let _saving = self.saving.lock().await;
+        let store = cookies.lock().unwrap().clone();
+        self.write(&store).await
+    }
+
+    async fn write(&self, store: &CookieStore) -> Result<()> {
+        let mut domains: HashMap<String, Vec<&Cookie<'static>>> = HashMap::new();
+        // session cookies are kept as well, the login of most sites depends on them
+        for cookie in store.iter_unexpired() {
+            domains
+                .entry(String::from(&cookie.domain))
+                .or_default()
+                .push(cookie);
+        }
+        let mut written = HashSet::new();
+        for (domain, cookies) in domains {
+            let plain = serde_json::to_vec(&cookies)?;
+            let path = self.dir.join(file_name(&domain));
+            write_atomic(&path, &self.encrypt(&plain)?).await?;
+            written.insert(path);
+        }
+        // the cookies of these domains expired or were removed
+        for entry in std::fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) == Some(EXTENSION)
+                && !written.contains(&path)
+            {
+                tokio::fs::remove_file(&path).await?;
+            }
+        }
+        Ok(())
+    }
+
+    fn read_file(&self, path: &Path) -> Result<Vec<Cookie<'static>>> {
+        let data = std::fs::read(path)?;
+        Ok(serde_json::from_slice(&self.decrypt(&data)?)?)
+    }
+
+    fn encrypt(&self, plain: &[u8]) -> Result<Vec<u8>> {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let cipher_text = self
+            .cipher
+            .encrypt(&nonce, plain)
+            .map_err(|_| TErrorKind::CookieJar("Could not encrypt the cookies"))?;
+        Ok([nonce.as_slice(), &cipher_text].concat())
+    }
+
+    fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>> {
+        if data.len() < NONCE_LEN {
+            return Err(TErrorKind::CookieJar("The cookie file is too short").into());
+        }
+        let (nonce, cipher_text) = data.split_at(NONCE_LEN);
+        Ok(self
+            .cipher
+            .decrypt(XNonce::from_slice(nonce), cipher_text)
+            .map_err(|_| TErrorKind::CookieJar("Could not decrypt the cookies"))?)
+    }
+}
+
+fn file_name(domain: &str) -> String {
+    let domain: String = domain
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '.' | '-' => c,
+            _ => '_',
+        })
+        .collect();
+    match domain.trim_start_matches('.') {
+        "" => format!("_.{}", EXTENSION),
+        domain => format!("{}.{}", domain, EXTENSION),
+    }
+}
+
+// One key per folder. A key file of an older version is moved into the keyring.
+fn keyring_key(dir: &Path) -> Result<Key> {
+    let entry = keyring::Entry::new(KEYRING_SERVICE, &dir.to_string_lossy())?;
+    match entry.get_password() {
+        Ok(hex) => decode_key(&hex),
+        Err(keyring::Error::NoEntry) => {
+            let key_path = dir.join(KEY_FILE);
+            let key = if key_path.exists() {
+                file_key(dir)?
+            } else {
+                XChaCha20Poly1305::generate_key(&mut OsRng)
+            };
+            entry.set_password(&encode_key(&key))?;
+            if key_path.exists() {
+                std::fs::remove_file(&key_path)?;
+            }
+            Ok(key)
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+fn file_key(dir: &Path) -> Result<Key> {
+    let key_path = dir.join(KEY_FILE);
+    match std::fs::read(&key_path) {
+        Ok(key) if key.len() == 32 => Ok(*Key::from_slice(&key)),
+        Ok(_) => Err(TErrorKind::CookieJar("The key has the wrong length").into()),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            let key = XChaCha20Poly1305::generate_key(&mut OsRng);
+            write_key(&key_path, &key)?;
+            Ok(key)
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+// the keyring only stores strings
+fn encode_key(key: &Key) -> String {
+    key.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn decode_key(hex: &str) -> Result<Key> {
+    let bytes = (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            hex.get(i..i + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+        })
+        .collect::<Option<Vec<u8>>>()
+        .filter(|bytes| bytes.len() == 32)
+        .ok_or(TErrorKind::CookieJar("The key in the keyring is malformed"))?;
+    Ok(*Key::from_slice(&bytes))
+}
+
+// only the user may read the key
+fn write_key(path: &Path, key: &Key) -> Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    std::io::Write::write_all(&mut options.open(path)?, key.as_slice())?;
+    Ok(())
+}
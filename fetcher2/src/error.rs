@@ -1,24 +1,145 @@
 use std::backtrace::Backtrace;
+use std::fmt;
+use std::path::PathBuf;
 
+use reqwest::{Method, StatusCode};
 use thiserror::Error;
 use tokio::time::error::Elapsed;
 
+use crate::template::NodeIndex;
+
 pub type Result<T> = std::result::Result<T, TError>;
 
-#[derive(Error, Debug)]
-#[error("{kind:?}")]
+#[derive(Debug)]
 pub struct TError {
     pub kind: TErrorKind,
+    // innermost first, e.g. the url, then the task and then the module
+    pub context: Vec<ErrorContext>,
+    pub status: Option<StatusCode>,
     pub backtrace: Backtrace,
 }
 
 impl TError {
     pub fn new(kind: TErrorKind) -> Self {
+        let (status, url) = match &kind {
+            TErrorKind::ClientError(err) => (err.status(), err.url().map(|url| url.to_string())),
+            _ => (None, None),
+        };
         Self {
             kind,
+            context: url.map(ErrorContext::Url).into_iter().collect(),
+            status,
             backtrace: Backtrace::capture(),
         }
     }
+
+    pub fn context(mut self, context: ErrorContext) -> Self {
+        if !self.context.contains(&context) {
+            self.context.push(context);
+        }
+        self
+    }
+
+    pub fn with_status(mut self, status: StatusCode) -> Self {
+        self.status = Some(status);
+        self
+    }
+
+    // temporary failures, sending the same request again might work
+    pub fn is_retryable(&self) -> bool {
+        if let Some(status) = self.status {
+            return is_retryable_status(status);
+        }
+        match &self.kind {
+            TErrorKind::TimeOut(_) => true,
+            TErrorKind::ClientError(err) => {
+                err.is_timeout() || err.is_connect() || err.is_request() || err.is_body()
+            }
+            TErrorKind::FileError(err) => matches!(
+                err.kind(),
+                std::io::ErrorKind::ConnectionReset
+                    | std::io::ErrorKind::ConnectionAborted
+                    | std::io::ErrorKind::TimedOut
+                    | std::io::ErrorKind::Interrupted
+            ),
+            _ => false,
+        }
+    }
+}
+
+pub fn is_retryable_status(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::REQUEST_TIMEOUT
+            | StatusCode::TOO_MANY_REQUESTS
+            | StatusCode::INTERNAL_SERVER_ERROR
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+// sending these twice does no harm, e.g. unlike a login form
+pub fn is_idempotent(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::PUT | Method::DELETE | Method::OPTIONS | Method::TRACE
+    ) || method.as_str() == "PROPFIND"
+}
+
+// e.g. "Got unexpected data from server (parsing course name, url https://..., module Moodle)"
+impl fmt::Display for TError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.kind)?;
+        let mut parts: Vec<String> = self
+            .status
+            .iter()
+            .map(|status| format!("status {}", status))
+            .collect();
+        parts.extend(self.context.iter().map(ToString::to_string));
+        if !parts.is_empty() {
+            write!(f, " ({})", parts.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for TError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.kind)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ErrorContext {
+    Url(String),
+    // relative to the folder of the site
+    Task(PathBuf),
+    Path(PathBuf),
+    Node(Vec<usize>),
+    Module(String),
+    Operation(&'static str),
+    Parse(&'static str),
+}
+
+impl ErrorContext {
+    pub fn node(index: &NodeIndex) -> Self {
+        Self::Node(index.iter().copied().collect())
+    }
+}
+
+impl fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Url(url) => write!(f, "url {}", url),
+            Self::Task(path) => write!(f, "task {}", path.display()),
+            Self::Path(path) => write!(f, "path {}", path.display()),
+            Self::Node(index) => write!(f, "node {:?}", index),
+            Self::Module(module) => write!(f, "module {}", module),
+            Self::Operation(operation) => write!(f, "while {}", operation),
+            Self::Parse(step) => write!(f, "parsing {}", step),
+        }
+    }
 }
 
 impl<T> From<T> for TError
@@ -47,6 +168,48 @@ pub enum TErrorKind {
     #[error("The Etag was not well formatted")]
     ETagFormat,
 
+    #[error("Could not rename folder, {0:?} already exists")]
+    RenameTargetExists(std::path::PathBuf),
+
+    #[error("Invalid tag expression: {0}")]
+    TagExpression(String),
+
+    #[error("Template has version {found}, but only up to {supported} is supported")]
+    TemplateVersion { found: u64, supported: u64 },
+
+    #[error("Template could not be parsed: {0}")]
+    TemplateSyntax(String),
+
+    #[error("Hook failed with exit code {:?}: {}", .0.exit_code, .0.stderr)]
+    HookFailed(crate::template::node_type::hook::HookOutput),
+
+    #[error("Archive entry {0:?} would be extracted outside of its folder")]
+    UnsafeArchivePath(String),
+
+    #[error("Could not extract archive, {0:?} already exists")]
+    ExtractTargetExists(std::path::PathBuf),
+
+    #[error("Some nodes could not be prepared")]
+    PrepareFailed,
+
+    #[error("The run was canceled")]
+    Canceled,
+
+    #[error("Cookie jar error: {0}")]
+    CookieJar(&'static str),
+
+    #[error("Invalid header: {0}")]
+    InvalidHeader(String),
+
+    #[error("No recorded response for {0}")]
+    MissingFixture(String),
+
+    #[error("Logging error: {0}")]
+    Logging(String),
+
+    #[error("{0} is not a valid port")]
+    InvalidPort(u64),
+
     #[error("Xml error: {0}")]
     Xml(String),
 
@@ -56,6 +219,9 @@ pub enum TErrorKind {
     #[error("Client Error")]
     ClientError(#[from] reqwest::Error),
 
+    #[error("Http Error")]
+    HttpError(#[from] http::Error),
+
     #[error("Timeout error")]
     TimeOut(#[from] Elapsed),
 
@@ -65,16 +231,82 @@ pub enum TErrorKind {
     #[error("Serde Error")]
     SerdeError(#[from] ron::Error),
 
+    #[error("Zip Error")]
+    ZipError(#[from] zip::result::ZipError),
+
+    #[error("Json Error")]
+    JsonError(#[from] serde_json::Error),
+
     #[error("Config Error")]
     ConfigError(#[from] config::errors::Error),
+
+    #[error("Keyring Error")]
+    Keyring(#[from] keyring::Error),
+
+    #[error("Task Error")]
+    JoinError(#[from] tokio::task::JoinError),
+}
+
+impl TErrorKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::PreviousLoginError => "previous_login_error",
+            Self::LoginDataRequired => "login_data_required",
+            Self::LoginError => "login_error",
+            Self::WrongFormat => "wrong_format",
+            Self::ETagFormat => "etag_format",
+            Self::RenameTargetExists(_) => "rename_target_exists",
+            Self::TagExpression(_) => "tag_expression",
+            Self::TemplateVersion { .. } => "template_version",
+            Self::TemplateSyntax(_) => "template_syntax",
+            Self::HookFailed(_) => "hook_failed",
+            Self::UnsafeArchivePath(_) => "unsafe_archive_path",
+            Self::ExtractTargetExists(_) => "extract_target_exists",
+            Self::PrepareFailed => "prepare_failed",
+            Self::Canceled => "canceled",
+            Self::CookieJar(_) => "cookie_jar",
+            Self::InvalidHeader(_) => "invalid_header",
+            Self::MissingFixture(_) => "missing_fixture",
+            Self::Logging(_) => "logging",
+            Self::InvalidPort(_) => "invalid_port",
+            Self::Xml(_) => "xml",
+            Self::UrlParseError(_) => "url_parse_error",
+            Self::ClientError(_) => "client_error",
+            Self::HttpError(_) => "http_error",
+            Self::TimeOut(_) => "time_out",
+            Self::FileError(_) => "file_error",
+            Self::SerdeError(_) => "serde_error",
+            Self::ZipError(_) => "zip_error",
+            Self::JsonError(_) => "json_error",
+            Self::ConfigError(_) => "config_error",
+            Self::Keyring(_) => "keyring",
+            Self::JoinError(_) => "join_error",
+        }
+    }
 }
 
 pub trait TErrorFast<T> {
-    fn wrong_format(self) -> Result<T>;
+    fn wrong_format(self, step: &'static str) -> Result<T>;
 }
 
 impl<T> TErrorFast<T> for Option<T> {
-    fn wrong_format(self) -> Result<T> {
-        self.ok_or_else(|| TError::new(TErrorKind::WrongFormat))
+    fn wrong_format(self, step: &'static str) -> Result<T> {
+        self.ok_or_else(|| TError::new(TErrorKind::WrongFormat).context(ErrorContext::Parse(step)))
+    }
+}
+
+pub trait TErrorContext<T> {
+    fn context(self, context: ErrorContext) -> Result<T>;
+
+    fn with_context(self, context: impl FnOnce() -> ErrorContext) -> Result<T>;
+}
+
+impl<T, E: Into<TError>> TErrorContext<T> for std::result::Result<T, E> {
+    fn context(self, context: ErrorContext) -> Result<T> {
+        self.map_err(|err| err.into().context(context))
+    }
+
+    fn with_context(self, context: impl FnOnce() -> ErrorContext) -> Result<T> {
+        self.map_err(|err| err.into().context(context()))
     }
 }
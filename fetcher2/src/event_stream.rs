@@ -0,0 +1,237 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::error::{Result, TError};
+use crate::template::node_type::extract::{ExtractEventKind, ExtractMsg};
+use crate::template::node_type::hook::{HookEventKind, HookOutput};
+use crate::template::node_type::site::{
+    DownloadEventKind, DownloadProgress, LoginEventKind, RunEventKind, SiteEventKind, TaskMsg,
+    UrlFetchEventKind,
+};
+use crate::template::nodes::node::{NodeEvent, NodeEventKind, PathEventKind};
+use crate::template::NodeIndex;
+
+// One line of the event stream
+#[derive(Serialize, Debug, Clone)]
+pub struct EventRecord {
+    pub time: DateTime<Utc>,
+    pub index: Vec<usize>,
+    pub path: Option<PathBuf>,
+    pub kind: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub progress: Option<DownloadProgress>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<TaskMsg>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hook: Option<HookOutput>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extract: Option<ExtractMsg>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warning: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub waited_secs: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorRecord>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ErrorRecord {
+    pub kind: &'static str,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    // innermost first
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub context: Vec<String>,
+    pub retryable: bool,
+}
+
+impl From<&TError> for ErrorRecord {
+    fn from(err: &TError) -> Self {
+        Self {
+            kind: err.kind.name(),
+            message: err.kind.to_string(),
+            status: err.status.map(|status| status.as_u16()),
+            context: err.context.iter().map(ToString::to_string).collect(),
+            retryable: err.is_retryable(),
+        }
+    }
+}
+
+impl EventRecord {
+    pub fn new(event: &NodeEvent, path: Option<&Path>) -> Self {
+        let mut record = Self {
+            time: Utc::now(),
+            index: event.idx.iter().copied().collect(),
+            path: path.map(Path::to_path_buf),
+            kind: kind_name(&event.kind),
+            progress: None,
+            file: None,
+            hook: None,
+            extract: None,
+            warning: None,
+            waited_secs: None,
+            error: None,
+        };
+        match &event.kind {
+            NodeEventKind::Path(PathEventKind::Cached(path) | PathEventKind::Finish(path)) => {
+                record.path = Some(path.clone())
+            }
+            NodeEventKind::Site(SiteEventKind::Download(DownloadEventKind::Progress(progress))) => {
+                record.progress = Some(progress.clone())
+            }
+            NodeEventKind::Site(SiteEventKind::Download(DownloadEventKind::Finish(msg))) => {
+                record.file = Some(msg.clone())
+            }
+            NodeEventKind::Site(SiteEventKind::Hook(HookEventKind::Finish(output))) => {
+                record.hook = Some(output.clone())
+            }
+            NodeEventKind::Site(SiteEventKind::Extract(ExtractEventKind::Finish(msg))) => {
+                record.extract = Some(msg.clone())
+            }
+            NodeEventKind::Site(SiteEventKind::Warning(warning)) => {
+                record.warning = Some(warning.clone())
+            }
+            NodeEventKind::Site(SiteEventKind::RateLimitWait(waited)) => {
+                record.waited_secs = Some(waited.as_secs_f64())
+            }
+            _ => (),
+        }
+        record.error = event_error(&event.kind).map(|err| ErrorRecord::from(err.as_ref()));
+        record
+    }
+}
+
+fn kind_name(kind: &NodeEventKind) -> &'static str {
+    match kind {
+        NodeEventKind::Path(PathEventKind::Start) => "path_start",
+        NodeEventKind::Path(PathEventKind::Cached(_)) => "path_cached",
+        NodeEventKind::Path(PathEventKind::Finish(_)) => "path_finish",
+        NodeEventKind::Path(PathEventKind::Err(_)) => "path_error",
+        NodeEventKind::Canceled => "canceled",
+        NodeEventKind::Disabled => "disabled",
+        NodeEventKind::Site(site_event) => match site_event {
+            SiteEventKind::Run(RunEventKind::Start) => "run_start",
+            SiteEventKind::Run(RunEventKind::Finish) => "run_finish",
+            SiteEventKind::Login(LoginEventKind::Start) => "login_start",
+            SiteEventKind::Login(LoginEventKind::Finish) => "login_finish",
+            SiteEventKind::Login(LoginEventKind::Err(_)) => "login_error",
+            SiteEventKind::UrlFetch(UrlFetchEventKind::Start) => "url_fetch_start",
+            SiteEventKind::UrlFetch(UrlFetchEventKind::Finish) => "url_fetch_finish",
+            SiteEventKind::UrlFetch(UrlFetchEventKind::Err(_)) => "url_fetch_error",
+            SiteEventKind::Download(DownloadEventKind::Start) => "download_start",
+            SiteEventKind::Download(DownloadEventKind::Progress(_)) => "download_progress",
+            SiteEventKind::Download(DownloadEventKind::Finish(_)) => "download_finish",
+            SiteEventKind::Download(DownloadEventKind::Err(_)) => "download_error",
+            SiteEventKind::Hook(HookEventKind::Start) => "hook_start",
+            SiteEventKind::Hook(HookEventKind::Finish(_)) => "hook_finish",
+            SiteEventKind::Hook(HookEventKind::Err(_)) => "hook_error",
+            SiteEventKind::Extract(ExtractEventKind::Start) => "extract_start",
+            SiteEventKind::Extract(ExtractEventKind::Finish(_)) => "extract_finish",
+            SiteEventKind::Extract(ExtractEventKind::Err(_)) => "extract_error",
+            SiteEventKind::Warning(_) => "warning",
+            SiteEventKind::RateLimitWait(_) => "rate_limit_wait",
+        },
+    }
+}
+
+fn event_error(kind: &NodeEventKind) -> Option<&Arc<TError>> {
+    match kind {
+        NodeEventKind::Path(PathEventKind::Err(err))
+        | NodeEventKind::Site(
+            SiteEventKind::Login(LoginEventKind::Err(err))
+            | SiteEventKind::UrlFetch(UrlFetchEventKind::Err(err))
+            | SiteEventKind::Download(DownloadEventKind::Err(err))
+            | SiteEventKind::Hook(HookEventKind::Err(err))
+            | SiteEventKind::Extract(ExtractEventKind::Err(err)),
+        ) => Some(err),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EventTarget {
+    Stdout,
+    File(PathBuf),
+    UnixSocket(PathBuf),
+}
+
+// "-" is stdout, "unix:<path>" connects to a listening socket, everything else is a file
+impl FromStr for EventTarget {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Ok(match s {
+            "-" => Self::Stdout,
+            _ => match s.strip_prefix("unix:") {
+                Some(path) => Self::UnixSocket(PathBuf::from(path)),
+                None => Self::File(PathBuf::from(s)),
+            },
+        })
+    }
+}
+
+// Writes every event as one json line.
+// The paths of the nodes are remembered from their path events.
+pub struct EventSink {
+    writer: Box<dyn AsyncWrite + Send + Unpin>,
+    paths: HashMap<NodeIndex, PathBuf>,
+}
+
+impl EventSink {
+    pub async fn open(target: &EventTarget) -> Result<Self> {
+        let writer: Box<dyn AsyncWrite + Send + Unpin> = match target {
+            EventTarget::Stdout => Box::new(tokio::io::stdout()),
+            EventTarget::File(path) => Box::new(
+                tokio::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .await?,
+            ),
+            EventTarget::UnixSocket(path) => Box::new(connect_unix(path).await?),
+        };
+        Ok(Self::new(writer))
+    }
+
+    pub fn new(writer: Box<dyn AsyncWrite + Send + Unpin>) -> Self {
+        Self {
+            writer,
+            paths: HashMap::new(),
+        }
+    }
+
+    pub async fn write(&mut self, event: &NodeEvent) -> Result<()> {
+        let path = self.paths.get(&event.idx).map(PathBuf::as_path);
+        let record = EventRecord::new(event, path);
+        if let Some(path) = &record.path {
+            self.paths.insert(event.idx.clone(), path.clone());
+        }
+        let mut line = serde_json::to_vec(&record)?;
+        line.push(b'\n');
+        self.writer.write_all(&line).await?;
+        // consumers are tailing the stream
+        self.writer.flush().await?;
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+async fn connect_unix(path: &Path) -> Result<tokio::net::UnixStream> {
+    Ok(tokio::net::UnixStream::connect(path).await?)
+}
+
+#[cfg(not(unix))]
+async fn connect_unix(_path: &Path) -> Result<tokio::io::Stdout> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "Unix sockets are not supported on this platform",
+    )
+    .into())
+}
@@ -0,0 +1,196 @@
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use futures::future::{AbortHandle, Abortable};
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use tokio::sync::mpsc::Receiver;
+use tokio::sync::RwLock;
+
+use crate::error::{Result, TErrorKind};
+use crate::session::Session;
+use crate::settings::DownloadSettings;
+use crate::template::communication::EventBus;
+use crate::template::nodes::node::{NodeEvent, Status};
+use crate::template::report::RunReport;
+use crate::template::tags::TagExpr;
+use crate::template::{run_with_report, NodeIndex, Template, UnPrepared};
+
+pub type EventStream = BoxStream<'static, NodeEvent>;
+
+#[derive(Debug, Clone)]
+pub enum Selection {
+    All,
+    Indexes(HashSet<NodeIndex>),
+    Tags(TagExpr),
+}
+
+pub struct FetcherBuilder {
+    settings: DownloadSettings,
+    template_path: PathBuf,
+    session: Option<Session>,
+}
+
+impl FetcherBuilder {
+    pub fn new(settings: DownloadSettings, template_path: impl Into<PathBuf>) -> Self {
+        Self {
+            settings,
+            template_path: template_path.into(),
+            session: None,
+        }
+    }
+
+    pub fn session(mut self, session: Session) -> Self {
+        self.session = Some(session);
+        self
+    }
+
+    pub async fn build(self) -> Result<Fetcher> {
+        let session = match self.session {
+            Some(session) => session,
+            None => Session::new(&self.settings)?,
+        };
+        // an unclaimed receiver would block the runs as soon as it is full,
+        // events creates its own
+        let (template, _) = Template::load(&self.template_path).await?;
+        Ok(Fetcher {
+            bus: template.bus.clone(),
+            settings: Arc::new(self.settings),
+            session,
+            template: RwLock::new(template),
+            events_taken: AtomicBool::new(false),
+            running: Mutex::new(HashMap::new()),
+            next_run: AtomicU64::new(0),
+        })
+    }
+}
+
+// Owns everything needed for a run. Share it with an Arc to cancel from another task.
+pub struct Fetcher {
+    settings: Arc<DownloadSettings>,
+    session: Session,
+    bus: EventBus,
+    template: RwLock<Template<UnPrepared>>,
+    events_taken: AtomicBool,
+    // the prepares and runs that haven't finished yet
+    running: Mutex<HashMap<u64, AbortHandle>>,
+    next_run: AtomicU64,
+}
+
+impl Fetcher {
+    pub fn builder(
+        settings: DownloadSettings,
+        template_path: impl Into<PathBuf>,
+    ) -> FetcherBuilder {
+        FetcherBuilder::new(settings, template_path)
+    }
+
+    pub fn settings(&self) -> &DownloadSettings {
+        &self.settings
+    }
+
+    // Receives every event of the prepares and runs started after this call.
+    // Can only be taken once, use subscribe for more streams.
+    // The streams end when the Fetcher is dropped.
+    pub fn events(&self) -> Option<EventStream> {
+        if self.events_taken.swap(true, Ordering::Relaxed) {
+            return None;
+        }
+        Some(self.subscribe())
+    }
+
+    pub fn subscribe(&self) -> EventStream {
+        into_stream(self.bus.subscribe())
+    }
+
+    // progress events are skipped while the stream is behind
+    pub fn subscribe_lossy(&self) -> EventStream {
+        into_stream(self.bus.subscribe_lossy())
+    }
+
+    pub async fn prepare(&self) -> Result<()> {
+        self.abortable(self.prepare_indexes(None)).await?
+    }
+
+    pub async fn run(&self, selection: Selection) -> Result<RunReport> {
+        self.abortable(self.run_selection(selection)).await?
+    }
+
+    pub async fn cancel(&self) {
+        for (_, handle) in self.running.lock().unwrap().drain() {
+            handle.abort();
+        }
+        self.template.read().await.inform_of_cancel().await;
+    }
+
+    pub async fn save(&self) -> Result<()> {
+        self.template.read().await.save().await
+    }
+
+    async fn abortable<T>(&self, fut: impl Future<Output = T>) -> Result<T> {
+        let (handle, registration) = AbortHandle::new_pair();
+        let id = self.next_run.fetch_add(1, Ordering::Relaxed);
+        self.running.lock().unwrap().insert(id, handle);
+        let _running = Running {
+            running: &self.running,
+            id,
+        };
+        Abortable::new(fut, registration)
+            .await
+            .map_err(|_| TErrorKind::Canceled.into())
+    }
+
+    async fn prepare_indexes(&self, indexes: Option<&HashSet<NodeIndex>>) -> Result<()> {
+        let mut template = self.template.write().await;
+        if template.root.is_prepared(indexes) {
+            return Ok(());
+        }
+        match template
+            .root
+            .prepare(&self.session, Arc::clone(&self.settings), indexes)
+            .await
+        {
+            Status::Success => Ok(()),
+            Status::Failure => Err(TErrorKind::PrepareFailed.into()),
+        }
+    }
+
+    async fn run_selection(&self, selection: Selection) -> Result<RunReport> {
+        let indexes = match selection {
+            Selection::All => None,
+            Selection::Indexes(indexes) => Some(indexes),
+            Selection::Tags(expr) => Some(self.template.read().await.select(&expr)),
+        };
+        self.prepare_indexes(indexes.as_ref()).await?;
+        let template = self.template.read().await;
+        Ok(run_with_report(
+            &template.root,
+            &self.session,
+            Arc::clone(&self.settings),
+            indexes.as_ref(),
+        )
+        .await)
+    }
+}
+
+// removes the abort handle once its future is done or dropped
+struct Running<'a> {
+    running: &'a Mutex<HashMap<u64, AbortHandle>>,
+    id: u64,
+}
+
+impl Drop for Running<'_> {
+    fn drop(&mut self) {
+        self.running.lock().unwrap().remove(&self.id);
+    }
+}
+
+fn into_stream(rx: Receiver<NodeEvent>) -> EventStream {
+    futures::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|event| (event, rx))
+    })
+    .boxed()
+}
@@ -6,8 +6,17 @@
 #![allow(clippy::new_without_default)]
 
 pub use error::{Result, TError, TErrorKind};
+pub use fetcher::{Fetcher, FetcherBuilder, Selection};
 
+pub mod cookies;
 pub mod error;
+pub mod event_stream;
+pub mod fetcher;
+pub mod logging;
+pub mod metrics;
+pub mod notifier;
+pub mod rate_limit;
+pub mod replay;
 pub mod session;
 pub mod settings;
 pub mod site_modules;
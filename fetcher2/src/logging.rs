@@ -0,0 +1,114 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::filter::EnvFilter;
+use tracing_subscriber::fmt;
+use tracing_subscriber::prelude::*;
+
+use crate::error::{Result, TError, TErrorKind};
+
+// takes precedence over LogSettings::filter, same syntax
+pub const LOG_ENV: &str = "FETCHER2_LOG";
+
+#[derive(Debug, Clone)]
+pub struct LogSettings {
+    // directives like "info,fetcher2::session=debug"
+    pub filter: String,
+    // stdout is left to the actual output of the cli
+    pub stderr: bool,
+    pub file: Option<LogFile>,
+}
+
+impl Default for LogSettings {
+    fn default() -> Self {
+        Self {
+            filter: "info".to_owned(),
+            stderr: true,
+            file: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LogFile {
+    pub dir: PathBuf,
+    pub rotation: LogRotation,
+}
+
+impl LogFile {
+    pub fn default_dir() -> Option<PathBuf> {
+        directories::ProjectDirs::from("ch", "fetcher2", "fetcher2")
+            .map(|dirs| dirs.data_dir().join("logs"))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogRotation {
+    Hourly,
+    Daily,
+    Never,
+}
+
+impl FromStr for LogRotation {
+    type Err = TError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "hourly" => Ok(Self::Hourly),
+            "daily" => Ok(Self::Daily),
+            "never" => Ok(Self::Never),
+            _ => Err(TErrorKind::Logging(format!(
+                "Unknown rotation {:?}, use hourly, daily or never",
+                s
+            ))
+            .into()),
+        }
+    }
+}
+
+impl From<LogRotation> for Rotation {
+    fn from(rotation: LogRotation) -> Self {
+        match rotation {
+            LogRotation::Hourly => Rotation::HOURLY,
+            LogRotation::Daily => Rotation::DAILY,
+            LogRotation::Never => Rotation::NEVER,
+        }
+    }
+}
+
+// The remaining file logs are written when this is dropped
+pub struct LogGuard {
+    _file: Option<WorkerGuard>,
+}
+
+// Sets the global subscriber, can only be called once
+pub fn init(settings: &LogSettings) -> Result<LogGuard> {
+    let filter = std::env::var(LOG_ENV).unwrap_or_else(|_| settings.filter.clone());
+    let filter = EnvFilter::try_new(&filter).map_err(|err| TErrorKind::Logging(err.to_string()))?;
+
+    let stderr = settings
+        .stderr
+        .then(|| fmt::layer().with_writer(std::io::stderr));
+
+    let (file, guard) = match &settings.file {
+        Some(log_file) => {
+            std::fs::create_dir_all(&log_file.dir)?;
+            let appender =
+                RollingFileAppender::new(log_file.rotation.into(), &log_file.dir, "fetcher2.log");
+            let (writer, guard) = tracing_appender::non_blocking(appender);
+            let layer = fmt::layer().with_ansi(false).with_writer(writer);
+            (Some(layer), Some(guard))
+        }
+        None => (None, None),
+    };
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(stderr)
+        .with(file)
+        .try_init()
+        .map_err(|err| TErrorKind::Logging(err.to_string()))?;
+    Ok(LogGuard { _file: guard })
+}
@@ -0,0 +1,370 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+use tracing::warn;
+
+use crate::error::Result;
+use crate::template::report::RunReport;
+use crate::utils::write_atomic;
+
+// upper bounds in seconds, shared by every histogram
+const BUCKETS: [f64; 12] = [0.05, 0.1, 0.25, 0.5, 1., 2.5, 5., 10., 30., 60., 300., 900.];
+
+lazy_static! {
+    static ref METRICS: Metrics = Metrics::new();
+}
+
+// Everything since the start of the program, the requests of all sessions end up here
+pub fn metrics() -> &'static Metrics {
+    &METRICS
+}
+
+#[derive(Default)]
+pub struct Metrics {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    // host, status
+    requests: BTreeMap<(String, String), u64>,
+    retries: BTreeMap<String, u64>,
+    request_duration: BTreeMap<String, Histogram>,
+    // module, result
+    files: BTreeMap<(String, &'static str), u64>,
+    bytes: BTreeMap<String, u64>,
+    errors: BTreeMap<String, u64>,
+    site_duration: BTreeMap<String, Histogram>,
+    runs: u64,
+    run_duration: Histogram,
+    last_run: Option<DateTime<Utc>>,
+}
+
+#[derive(Default, Clone)]
+struct Histogram {
+    // not cumulative, the last one counts everything above the buckets
+    counts: [u64; BUCKETS.len() + 1],
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, secs: f64) {
+        let idx = BUCKETS
+            .iter()
+            .position(|bound| secs <= *bound)
+            .unwrap_or(BUCKETS.len());
+        self.counts[idx] += 1;
+        self.sum += secs;
+    }
+
+    fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // status is None if no response arrived
+    pub fn record_request(&self, host: &str, status: Option<u16>, duration: Duration) {
+        let status = status.map_or_else(|| "error".to_owned(), |status| status.to_string());
+        let mut inner = self.inner.lock().unwrap();
+        *inner.requests.entry((host.to_owned(), status)).or_default() += 1;
+        inner
+            .request_duration
+            .entry(host.to_owned())
+            .or_default()
+            .observe(duration.as_secs_f64());
+    }
+
+    pub fn record_retry(&self, host: &str) {
+        *self
+            .inner
+            .lock()
+            .unwrap()
+            .retries
+            .entry(host.to_owned())
+            .or_default() += 1;
+    }
+
+    pub fn record_run(&self, report: &RunReport) {
+        let mut inner = self.inner.lock().unwrap();
+        for site in &report.sites {
+            let module = &site.module;
+            for (result, count) in [
+                ("added", site.added.len()),
+                ("replaced", site.replaced.len()),
+                ("unchanged", site.unchanged),
+                ("forbidden", site.forbidden),
+            ] {
+                *inner.files.entry((module.clone(), result)).or_default() += count as u64;
+            }
+            *inner.bytes.entry(module.clone()).or_default() += site.bytes;
+            *inner.errors.entry(module.clone()).or_default() += site.errors.len() as u64;
+            if let Some(secs) = site.duration_secs() {
+                inner
+                    .site_duration
+                    .entry(module.clone())
+                    .or_default()
+                    .observe(secs);
+            }
+        }
+        inner.runs += 1;
+        let run_secs = (report.finished - report.started).num_milliseconds() as f64 / 1000.;
+        inner.run_duration.observe(run_secs);
+        inner.last_run = Some(report.finished);
+    }
+
+    // Prometheus text format
+    pub fn render(&self) -> String {
+        let inner = self.inner.lock().unwrap();
+        let mut out = String::new();
+
+        header(
+            &mut out,
+            "fetcher2_http_requests_total",
+            "counter",
+            "Requests sent, status is error if there was no response",
+        );
+        for ((host, status), count) in &inner.requests {
+            sample(
+                &mut out,
+                "fetcher2_http_requests_total",
+                &[("host", host.as_str()), ("status", status.as_str())],
+                *count as f64,
+            );
+        }
+
+        header(
+            &mut out,
+            "fetcher2_http_retries_total",
+            "counter",
+            "Requests sent again after a failure",
+        );
+        for (host, count) in &inner.retries {
+            sample(
+                &mut out,
+                "fetcher2_http_retries_total",
+                &[("host", host.as_str())],
+                *count as f64,
+            );
+        }
+
+        header(
+            &mut out,
+            "fetcher2_http_request_duration_seconds",
+            "histogram",
+            "Time until the response headers arrived",
+        );
+        for (host, histogram) in &inner.request_duration {
+            histogram_samples(
+                &mut out,
+                "fetcher2_http_request_duration_seconds",
+                &[("host", host.as_str())],
+                histogram,
+            );
+        }
+
+        header(
+            &mut out,
+            "fetcher2_files_total",
+            "counter",
+            "Files handled by the sites of a module",
+        );
+        for ((module, result), count) in &inner.files {
+            sample(
+                &mut out,
+                "fetcher2_files_total",
+                &[("module", module.as_str()), ("result", *result)],
+                *count as f64,
+            );
+        }
+
+        header(
+            &mut out,
+            "fetcher2_downloaded_bytes_total",
+            "counter",
+            "Bytes downloaded by the sites of a module",
+        );
+        for (module, bytes) in &inner.bytes {
+            sample(
+                &mut out,
+                "fetcher2_downloaded_bytes_total",
+                &[("module", module.as_str())],
+                *bytes as f64,
+            );
+        }
+
+        header(
+            &mut out,
+            "fetcher2_errors_total",
+            "counter",
+            "Errors of the sites of a module",
+        );
+        for (module, count) in &inner.errors {
+            sample(
+                &mut out,
+                "fetcher2_errors_total",
+                &[("module", module.as_str())],
+                *count as f64,
+            );
+        }
+
+        header(
+            &mut out,
+            "fetcher2_site_duration_seconds",
+            "histogram",
+            "Duration of a single site",
+        );
+        for (module, histogram) in &inner.site_duration {
+            histogram_samples(
+                &mut out,
+                "fetcher2_site_duration_seconds",
+                &[("module", module.as_str())],
+                histogram,
+            );
+        }
+
+        header(&mut out, "fetcher2_runs_total", "counter", "Finished runs");
+        sample(&mut out, "fetcher2_runs_total", &[], inner.runs as f64);
+
+        header(
+            &mut out,
+            "fetcher2_run_duration_seconds",
+            "histogram",
+            "Duration of a whole run",
+        );
+        histogram_samples(
+            &mut out,
+            "fetcher2_run_duration_seconds",
+            &[],
+            &inner.run_duration,
+        );
+
+        if let Some(last_run) = inner.last_run {
+            header(
+                &mut out,
+                "fetcher2_last_run_timestamp_seconds",
+                "gauge",
+                "When the last run finished",
+            );
+            sample(
+                &mut out,
+                "fetcher2_last_run_timestamp_seconds",
+                &[],
+                last_run.timestamp_millis() as f64 / 1000.,
+            );
+        }
+        out
+    }
+
+    // For the textfile collector of node_exporter, which must never see a half written file
+    pub async fn write_textfile(&self, path: &Path) -> Result<()> {
+        write_atomic(path, self.render().as_bytes()).await
+    }
+}
+
+fn header(out: &mut String, name: &str, ty: &str, help: &str) {
+    writeln!(out, "# HELP {} {}", name, help).unwrap();
+    writeln!(out, "# TYPE {} {}", name, ty).unwrap();
+}
+
+fn sample(out: &mut String, name: &str, labels: &[(&str, &str)], value: f64) {
+    out.push_str(name);
+    if !labels.is_empty() {
+        let labels: Vec<_> = labels
+            .iter()
+            .map(|(label, value)| format!("{}=\"{}\"", label, escape(value)))
+            .collect();
+        write!(out, "{{{}}}", labels.join(",")).unwrap();
+    }
+    writeln!(out, " {}", value).unwrap();
+}
+
+fn histogram_samples(out: &mut String, name: &str, labels: &[(&str, &str)], histogram: &Histogram) {
+    let bucket_name = format!("{}_bucket", name);
+    let mut cumulative = 0;
+    for (idx, count) in histogram.counts.iter().enumerate() {
+        cumulative += count;
+        let le = match BUCKETS.get(idx) {
+            Some(bound) => bound.to_string(),
+            None => "+Inf".to_owned(),
+        };
+        let mut bucket_labels = labels.to_vec();
+        bucket_labels.push(("le", le.as_str()));
+        sample(out, &bucket_name, &bucket_labels, cumulative as f64);
+    }
+    sample(out, &format!("{}_sum", name), labels, histogram.sum);
+    sample(
+        out,
+        &format!("{}_count", name),
+        labels,
+        histogram.count() as f64,
+    );
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+// Answers every request on the port with the current metrics, stops when dropped
+pub struct MetricsServer {
+    addr: SocketAddr,
+    handle: JoinHandle<()>,
+}
+
+impl MetricsServer {
+    // only reachable from the same machine
+    pub async fn start(port: u16) -> Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", port)).await?;
+        let addr = listener.local_addr()?;
+        let handle = tokio::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, _)) => {
+                        tokio::spawn(respond(stream));
+                    }
+                    Err(err) => warn!(error = %err, "Could not accept metrics request"),
+                }
+            }
+        });
+        Ok(Self { addr, handle })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+impl Drop for MetricsServer {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+async fn respond(mut stream: TcpStream) {
+    // the request itself does not matter, but it has to be read before answering
+    let mut buffer = [0u8; 1024];
+    let _ = stream.read(&mut buffer).await;
+    let body = metrics().render();
+    let response = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        body.len(),
+        body
+    );
+    let _ = stream.write_all(response.as_bytes()).await;
+}
@@ -0,0 +1,238 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use tracing::warn;
+
+use config::traveller::Travel;
+
+use crate::error::Result;
+use crate::template::node_type::site::{DownloadEventKind, MsgKind, SiteEventKind, TaskMsg};
+use crate::template::nodes::node::{NodeEvent, NodeEventKind};
+use crate::template::report::RunReport;
+use crate::template::RunId;
+
+#[cfg_attr(feature = "druid", derive(druid::Data))]
+#[derive(Travel, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WebhookSettings {
+    #[travel(name = "Url")]
+    pub url: String,
+
+    /// Body of the POST request. {{text}} is replaced by a summary,
+    /// {{count}} by the number of events and {{events}} by a json array.
+    /// Slack: {"text": "{{text}}"}, Discord: {"content": "{{text}}"},
+    /// Matrix: {"msgtype": "m.text", "body": "{{text}}"}
+    #[travel(name = "Body Template")]
+    pub body_template: Option<String>,
+
+    /// Events are collected for this long before they are sent together
+    #[travel(default = 5, name = "Batch Delay in Seconds")]
+    pub batch_delay: u64,
+
+    #[travel(default = 50, name = "Max Events per Request")]
+    pub batch_size: u64,
+
+    #[travel(default = 3, name = "Retries")]
+    pub retries: u64,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum WebhookEvent {
+    FileAdded {
+        path: PathBuf,
+        time: Option<DateTime<Utc>>,
+    },
+    FileReplaced {
+        path: PathBuf,
+        time: Option<DateTime<Utc>>,
+    },
+    RunFinished {
+        run_id: RunId,
+        added: usize,
+        replaced: usize,
+        errors: usize,
+    },
+}
+
+impl WebhookEvent {
+    fn text(&self) -> String {
+        match self {
+            Self::FileAdded { path, .. } => format!("New file: {}", path.display()),
+            Self::FileReplaced { path, .. } => format!("Updated file: {}", path.display()),
+            Self::RunFinished {
+                added,
+                replaced,
+                errors,
+                ..
+            } => format!(
+                "Run finished: {} new, {} updated, {} errors",
+                added, replaced, errors
+            ),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Payload<'a> {
+    text: String,
+    events: &'a [WebhookEvent],
+}
+
+// Sending never blocks, the requests are made by a background task
+#[derive(Debug, Clone)]
+pub struct WebhookNotifier {
+    tx: mpsc::UnboundedSender<WebhookEvent>,
+}
+
+impl WebhookNotifier {
+    pub fn new(settings: WebhookSettings) -> (Self, JoinHandle<()>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let handle = tokio::spawn(deliver_loop(settings, Client::new(), rx));
+        (Self { tx }, handle)
+    }
+
+    pub fn notify_event(&self, event: &NodeEvent) {
+        if let NodeEventKind::Site(SiteEventKind::Download(DownloadEventKind::Finish(msg))) =
+            &event.kind
+        {
+            self.notify_msg(msg)
+        }
+    }
+
+    pub fn notify_msg(&self, msg: &TaskMsg) {
+        let event = match msg.kind {
+            MsgKind::AddedFile => WebhookEvent::FileAdded {
+                path: msg.rel_path.clone(),
+                time: msg.time,
+            },
+            MsgKind::ReplacedFile(_) => WebhookEvent::FileReplaced {
+                path: msg.rel_path.clone(),
+                time: msg.time,
+            },
+            _ => return,
+        };
+        self.send(event)
+    }
+
+    pub fn run_finished(&self, report: &RunReport) {
+        self.send(WebhookEvent::RunFinished {
+            run_id: report.run_id,
+            added: report.added(),
+            replaced: report.replaced(),
+            errors: report.errors(),
+        })
+    }
+
+    fn send(&self, event: WebhookEvent) {
+        // the background task only stops if all notifiers were dropped
+        let _ = self.tx.send(event);
+    }
+}
+
+// the background task sends the last batch after every notifier was dropped
+async fn deliver_loop(
+    settings: WebhookSettings,
+    client: Client,
+    mut rx: mpsc::UnboundedReceiver<WebhookEvent>,
+) {
+    let batch_size = settings.batch_size.max(1) as usize;
+    while let Some(first) = rx.recv().await {
+        let mut batch = vec![first];
+        let deadline = Instant::now() + Duration::from_secs(settings.batch_delay);
+        while batch.len() < batch_size && !is_run_finished(batch.last()) {
+            match tokio::time::timeout_at(deadline, rx.recv()).await {
+                Ok(Some(event)) => batch.push(event),
+                Ok(None) | Err(_) => break,
+            }
+        }
+        if let Err(err) = deliver(&settings, &client, &batch).await {
+            warn!(error = %err, "Could not deliver webhook");
+        }
+    }
+}
+
+fn is_run_finished(event: Option<&WebhookEvent>) -> bool {
+    matches!(event, Some(WebhookEvent::RunFinished { .. }))
+}
+
+async fn deliver(
+    settings: &WebhookSettings,
+    client: &Client,
+    batch: &[WebhookEvent],
+) -> Result<()> {
+    let body = render_body(settings.body_template.as_deref(), batch)?;
+    let mut backoff = Duration::from_secs(1);
+    let mut attempt = 0;
+    loop {
+        let result = client
+            .post(&settings.url)
+            .header("Content-Type", "application/json")
+            .body(body.clone())
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        match result {
+            Ok(_) => return Ok(()),
+            Err(err) if attempt >= settings.retries => return Err(err.into()),
+            Err(_) => {
+                attempt += 1;
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+        }
+    }
+}
+
+pub fn render_body(template: Option<&str>, batch: &[WebhookEvent]) -> Result<String> {
+    let text = batch
+        .iter()
+        .map(WebhookEvent::text)
+        .collect::<Vec<_>>()
+        .join("\n");
+    match template {
+        None => Ok(serde_json::to_string(&Payload {
+            text,
+            events: batch,
+        })?),
+        Some(template) => {
+            // the placeholders are meant to be used inside json strings
+            let text = serde_json::to_string(&text)?;
+            let text = &text[1..text.len() - 1];
+            let count = batch.len().to_string();
+            let events = serde_json::to_string(batch)?;
+
+            // in a single pass, so placeholders in file names stay as they are
+            let mut body = String::with_capacity(template.len());
+            let mut rest = template;
+            while let Some(start) = rest.find("{{") {
+                body.push_str(&rest[..start]);
+                rest = &rest[start..];
+                let value = [
+                    ("{{text}}", text),
+                    ("{{count}}", count.as_str()),
+                    ("{{events}}", events.as_str()),
+                ]
+                .into_iter()
+                .find(|(placeholder, _)| rest.starts_with(placeholder));
+                match value {
+                    Some((placeholder, value)) => {
+                        body.push_str(value);
+                        rest = &rest[placeholder.len()..];
+                    }
+                    None => {
+                        body.push_str("{{");
+                        rest = &rest[2..];
+                    }
+                }
+            }
+            body.push_str(rest);
+            Ok(body)
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::settings::RateLimit;
+
+pub struct Bucket {
+    limit: RateLimit,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    pub fn new(limit: RateLimit, now: Instant) -> Self {
+        Self {
+            limit,
+            tokens: limit.capacity(),
+            updated: now,
+        }
+    }
+
+    // Takes a token and returns how long to wait for it.
+    // The tokens can go negative, so concurrent requests queue up behind each other.
+    pub fn take(&mut self, now: Instant) -> Duration {
+        let refill = (now - self.updated).as_secs_f64() * self.limit.per_second;
+        self.tokens = (self.tokens + refill).min(self.limit.capacity());
+        self.updated = now;
+        self.tokens -= 1.;
+        if self.tokens >= 0. {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.limit.per_second)
+        }
+    }
+}
+
+// One token bucket per configured domain, or per host for the limits of the modules.
+// Shared by all clones of a session.
+#[derive(Clone, Default)]
+pub struct RateLimiter {
+    limits: Arc<HashMap<String, RateLimit>>,
+    buckets: Arc<Mutex<HashMap<String, Bucket>>>,
+}
+
+impl RateLimiter {
+    pub fn new(limits: HashMap<String, RateLimit>) -> Self {
+        Self {
+            limits: Arc::new(limits),
+            buckets: Arc::default(),
+        }
+    }
+
+    // the configured limits replace the fallback of the module
+    pub fn limit(&self, host: &str, fallback: Option<RateLimit>) -> Option<RateLimit> {
+        self.bucket_key(host, fallback).map(|(_, limit)| limit)
+    }
+
+    // the most specific configured domain, all of its subdomains share the bucket
+    fn bucket_key(&self, host: &str, fallback: Option<RateLimit>) -> Option<(String, RateLimit)> {
+        self.limits
+            .iter()
+            .filter(|(domain, _)| host_matches(host, domain))
+            .max_by_key(|(domain, _)| domain.len())
+            .map(|(domain, limit)| {
+                (
+                    domain.trim().trim_start_matches('.').to_ascii_lowercase(),
+                    *limit,
+                )
+            })
+            .or_else(|| fallback.map(|limit| (host.to_ascii_lowercase(), limit)))
+            .filter(|(_, limit)| limit.per_second > 0.)
+    }
+
+    pub async fn wait(&self, host: &str, fallback: Option<RateLimit>) -> Duration {
+        let (key, limit) = match self.bucket_key(host, fallback) {
+            Some(key) => key,
+            None => return Duration::ZERO,
+        };
+        let now = Instant::now();
+        let delay = {
+            let mut buckets = self.buckets.lock().unwrap();
+            let bucket = buckets
+                .entry(key)
+                .or_insert_with(|| Bucket::new(limit, now));
+            // modules with different fallbacks can share a host
+            bucket.limit = limit;
+            bucket.take(now)
+        };
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+        delay
+    }
+}
+
+// a domain also matches all of its subdomains
+pub fn host_matches(host: &str, domain: &str) -> bool {
+    let domain = domain.trim().trim_start_matches('.');
+    host.eq_ignore_ascii_case(domain)
+        || host
+            .to_ascii_lowercase()
+            .ends_with(&format!(".{}", domain.to_ascii_lowercase()))
+}
@@ -0,0 +1,318 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, RwLock};
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use reqwest::header::CONTENT_TYPE;
+use reqwest::{Request, Response, ResponseBuilderExt};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::error::{Result, TErrorKind};
+use crate::settings::DownloadSettings;
+use crate::utils::write_atomic;
+
+// never written to a fixture
+const SECRET_HEADERS: [&str; 4] = [
+    "authorization",
+    "proxy-authorization",
+    "cookie",
+    "set-cookie",
+];
+// the body is already decoded when it is recorded
+const SKIPPED_HEADERS: [&str; 2] = ["content-encoding", "transfer-encoding"];
+// form and query fields whose values are never written to a fixture
+const SECRET_FIELDS: [&str; 5] = [
+    "password",
+    "j_password",
+    "SAMLResponse",
+    "RelayState",
+    "requesttoken",
+];
+
+lazy_static! {
+    // the same fields as hidden inputs of html forms
+    static ref SECRET_INPUT_RE: Regex =
+        Regex::new(r#"name="(password|SAMLResponse|RelayState|requesttoken)" value="[^"]*""#)
+            .unwrap();
+    static ref REQUEST_TOKEN_RE: Regex = Regex::new(r#"data-requesttoken="[^"]*""#).unwrap();
+}
+
+// method, url and body of a request, without the credentials
+pub type RequestKey = (String, String, Option<String>);
+
+// One request and its response, stored as one json file
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Exchange {
+    pub method: String,
+    pub url: String,
+    #[serde(default)]
+    pub request_body: Option<String>,
+    pub status: u16,
+    // the url after all redirects, defaults to the request url
+    #[serde(default)]
+    pub final_url: Option<String>,
+    #[serde(default)]
+    pub headers: Vec<(String, String)>,
+    pub body: FixtureBody,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum FixtureBody {
+    Text(String),
+    Bytes(Vec<u8>),
+}
+
+impl FixtureBody {
+    fn new(bytes: Vec<u8>) -> Self {
+        match String::from_utf8(bytes) {
+            Ok(text) => Self::Text(text),
+            Err(err) => Self::Bytes(err.into_bytes()),
+        }
+    }
+
+    fn into_bytes(self) -> Vec<u8> {
+        match self {
+            Self::Text(text) => text.into_bytes(),
+            Self::Bytes(bytes) => bytes,
+        }
+    }
+}
+
+// Replaces the credentials and other secrets with placeholders,
+// so fixtures recorded by different users match the same requests
+#[derive(Debug)]
+struct Scrubber {
+    secrets: RwLock<Vec<(String, &'static str)>>,
+}
+
+impl Scrubber {
+    fn new(dsettings: &DownloadSettings) -> Self {
+        let scrubber = Self {
+            secrets: RwLock::default(),
+        };
+        for (value, placeholder) in [
+            (dsettings.password.as_deref(), "<password>"),
+            (dsettings.username.as_deref(), "<username>"),
+        ] {
+            if let Some(value) = value {
+                scrubber.add(value, placeholder);
+            }
+        }
+        scrubber
+    }
+
+    // e.g. the password of a share, only known to the module
+    fn add(&self, value: &str, placeholder: &'static str) {
+        if value.is_empty() {
+            return;
+        }
+        let mut secrets = self.secrets.write().unwrap();
+        // as is, in urls and in form bodies
+        let variants = [
+            value.to_owned(),
+            urlencoding::encode(value).into_owned(),
+            url::form_urlencoded::byte_serialize(value.as_bytes()).collect(),
+        ];
+        for variant in variants {
+            if !secrets.iter().any(|(secret, _)| secret == &variant) {
+                secrets.push((variant, placeholder));
+            }
+        }
+        // the longer secret first, in case one contains the other
+        secrets.sort_by_key(|(secret, _)| std::cmp::Reverse(secret.len()));
+    }
+
+    fn scrub(&self, text: &str) -> String {
+        let text = self
+            .secrets
+            .read()
+            .unwrap()
+            .iter()
+            .fold(text.to_owned(), |text, (secret, placeholder)| {
+                text.replace(secret.as_str(), placeholder)
+            });
+        let text = SECRET_INPUT_RE.replace_all(&text, r#"name="$1" value="<$1>""#);
+        REQUEST_TOKEN_RE
+            .replace_all(&text, r#"data-requesttoken="<requesttoken>""#)
+            .into_owned()
+    }
+
+    fn scrub_url(&self, url: &Url) -> String {
+        let mut url = url.clone();
+        if let Some(query) = url.query() {
+            let scrubbed = scrub_fields(query);
+            if scrubbed != query {
+                url.set_query(Some(&scrubbed));
+            }
+        }
+        self.scrub(url.as_str())
+    }
+
+    fn request_key(&self, request: &Request) -> RequestKey {
+        let is_form = request
+            .headers()
+            .get(CONTENT_TYPE)
+            .map_or(false, |value| value == "application/x-www-form-urlencoded");
+        let body = request
+            .body()
+            .and_then(|body| body.as_bytes())
+            .map(|bytes| {
+                let body = String::from_utf8_lossy(bytes);
+                if is_form {
+                    self.scrub(&scrub_fields(&body))
+                } else {
+                    self.scrub(&body)
+                }
+            });
+        (
+            request.method().to_string(),
+            self.scrub_url(request.url()),
+            body,
+        )
+    }
+}
+
+// a=1&password=2 becomes a=1&password=<password>
+fn scrub_fields(pairs: &str) -> String {
+    pairs
+        .split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some((name, _)) if SECRET_FIELDS.contains(&name) => format!("{}=<{}>", name, name),
+            _ => pair.to_owned(),
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+// Saves every exchange of a session to a fixture folder
+#[derive(Debug)]
+pub struct Recorder {
+    dir: PathBuf,
+    scrubber: Scrubber,
+    count: AtomicUsize,
+}
+
+impl Recorder {
+    pub fn new(dir: &Path, dsettings: &DownloadSettings) -> Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        Ok(Self {
+            dir: dir.to_owned(),
+            scrubber: Scrubber::new(dsettings),
+            count: AtomicUsize::new(0),
+        })
+    }
+
+    // The request must be taken before it is sent,
+    // the returned response replaces the consumed one
+    pub async fn record(&self, key: RequestKey, response: Response) -> Result<Response> {
+        let (method, url, request_body) = key;
+        let status = response.status().as_u16();
+        let final_url = self.scrubber.scrub_url(response.url());
+        let headers = response
+            .headers()
+            .iter()
+            .filter(|(name, _)| !SECRET_HEADERS.contains(&name.as_str()))
+            .filter(|(name, _)| !SKIPPED_HEADERS.contains(&name.as_str()))
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_owned())))
+            .collect();
+        let body = match FixtureBody::new(response.bytes().await?.to_vec()) {
+            FixtureBody::Text(text) => FixtureBody::Text(self.scrubber.scrub(&text)),
+            bytes => bytes,
+        };
+        let exchange = Exchange {
+            final_url: (final_url != url).then(|| final_url),
+            method,
+            url,
+            request_body,
+            status,
+            headers,
+            body,
+        };
+
+        let n = self.count.fetch_add(1, Ordering::SeqCst);
+        let path = self.dir.join(file_name(n, &exchange));
+        write_atomic(&path, &serde_json::to_vec_pretty(&exchange)?).await?;
+        exchange.into_response()
+    }
+
+    pub fn request_key(&self, request: &Request) -> RequestKey {
+        self.scrubber.request_key(request)
+    }
+
+    pub fn add_secret(&self, value: &str, placeholder: &'static str) {
+        self.scrubber.add(value, placeholder)
+    }
+}
+
+// Serves the fixtures of a folder without any network access.
+// Identical requests get the recorded responses in order, the last one is repeated.
+#[derive(Debug)]
+pub struct Replayer {
+    scrubber: Scrubber,
+    exchanges: Mutex<Vec<(Exchange, bool)>>,
+}
+
+impl Replayer {
+    pub fn new(dir: &Path, dsettings: &DownloadSettings) -> Result<Self> {
+        let mut paths = std::fs::read_dir(dir)?
+            .map(|entry| Ok(entry?.path()))
+            .collect::<Result<Vec<_>>>()?;
+        paths.retain(|path| path.extension().and_then(|ext| ext.to_str()) == Some("json"));
+        paths.sort();
+        let exchanges = paths
+            .iter()
+            .map(|path| Ok((serde_json::from_slice(&std::fs::read(path)?)?, false)))
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            scrubber: Scrubber::new(dsettings),
+            exchanges: Mutex::new(exchanges),
+        })
+    }
+
+    pub fn respond(&self, request: &Request) -> Result<Response> {
+        let (method, url, body) = self.scrubber.request_key(request);
+        let mut exchanges = self.exchanges.lock().unwrap();
+        let matching: Vec<usize> = exchanges
+            .iter()
+            .enumerate()
+            .filter(|(_, (exchange, _))| {
+                exchange.method == method && exchange.url == url && exchange.request_body == body
+            })
+            .map(|(i, _)| i)
+            .collect();
+        let i = matching
+            .iter()
+            .copied()
+            .find(|&i| !exchanges[i].1)
+            .or_else(|| matching.last().copied())
+            .ok_or_else(|| TErrorKind::MissingFixture(format!("{} {}", method, url)))?;
+        exchanges[i].1 = true;
+        exchanges[i].0.clone().into_response()
+    }
+
+    pub fn add_secret(&self, value: &str, placeholder: &'static str) {
+        self.scrubber.add(value, placeholder)
+    }
+}
+
+impl Exchange {
+    fn into_response(self) -> Result<Response> {
+        let url = Url::parse(self.final_url.as_ref().unwrap_or(&self.url))?;
+        let mut builder = http::Response::builder().status(self.status).url(url);
+        for (name, value) in &self.headers {
+            builder = builder.header(name.as_str(), value.as_str());
+        }
+        Ok(Response::from(builder.body(self.body.into_bytes())?))
+    }
+}
+
+fn file_name(n: usize, exchange: &Exchange) -> String {
+    let host = Url::parse(&exchange.url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_owned))
+        .unwrap_or_default();
+    format!("{:04}-{}-{}.json", n, exchange.method.to_lowercase(), host)
+}
@@ -1,19 +1,97 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
 use std::convert::TryFrom;
 use std::fmt::Display;
-use std::sync::Arc;
-use std::time::Duration;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
+use cookie_store::CookieStore;
 use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
-use reqwest::{Body, Client, ClientBuilder, IntoUrl, Method, Request, RequestBuilder, Response};
+use reqwest::{
+    Body, Certificate, Client, ClientBuilder, Identity, IntoUrl, Method, NoProxy, Proxy, Request,
+    RequestBuilder, Response,
+};
+use reqwest_cookie_store::CookieStoreMutex;
 use serde::Serialize;
+use sha1::{Digest, Sha1};
+use tracing::{debug, instrument, warn};
 
-use crate::error::Result;
+use crate::cookies::CookieJar;
+use crate::error::{
+    is_idempotent, is_retryable_status, ErrorContext, Result, TErrorContext, TErrorKind,
+};
+use crate::metrics::metrics;
+use crate::rate_limit::RateLimiter;
+use crate::replay::{Recorder, Replayer};
+use crate::settings::{DownloadSettings, ProxySettings, RateLimit, TlsSettings};
 use crate::site_modules::LoginLocks;
 
+const DEFAULT_USER_AGENT: &str = concat!("fetcher2/", env!("CARGO_PKG_VERSION"));
+
 #[derive(Clone)]
 pub struct Session {
     client: Client,
+    cookies: Arc<CookieStoreMutex>,
+    jar: Option<Arc<CookieJar>>,
+    headers: Arc<HeaderMap>,
+    limiter: RateLimiter,
+    // used for hosts without a configured limit
+    default_limit: Option<RateLimit>,
+    // milliseconds spent waiting for the rate limiter
+    waited: Arc<AtomicU64>,
+    retry_delay: Duration,
+    transport: Transport,
     pub login_mutex: Arc<LoginLocks>,
+    // the login locks and cookies belong to these
+    credentials: Credentials,
+    accounts: Arc<Accounts>,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct Credentials {
+    username: Option<String>,
+    password: Option<String>,
+}
+
+impl Credentials {
+    fn new(dsettings: &DownloadSettings) -> Self {
+        Self {
+            username: dsettings.username.clone(),
+            password: dsettings.password.clone(),
+        }
+    }
+
+    // names the cookie folder, so the password is left out
+    fn id(&self) -> String {
+        let mut hasher = Sha1::new();
+        hasher.update(self.username.as_deref().unwrap_or_default());
+        format!("{:x}", hasher.finalize())
+    }
+}
+
+// Sessions for credentials that differ from the ones the session was created with,
+// shared by all clones of it
+#[derive(Default)]
+struct Accounts {
+    jar: Option<Arc<CookieJar>>,
+    sessions: Mutex<HashMap<Credentials, Account>>,
+}
+
+#[derive(Clone)]
+struct Account {
+    client: Client,
+    cookies: Arc<CookieStoreMutex>,
+    jar: Option<Arc<CookieJar>>,
+    login_mutex: Arc<LoginLocks>,
+}
+
+#[derive(Clone)]
+enum Transport {
+    Network,
+    Record(Arc<Recorder>),
+    Replay(Arc<Replayer>),
 }
 
 pub struct SRequestBuilder {
@@ -21,24 +99,186 @@ pub struct SRequestBuilder {
     session: Session,
 }
 
-impl Default for Session {
-    fn default() -> Self {
-        Self::new()
+impl Session {
+    pub fn new(dsettings: &DownloadSettings) -> Result<Self> {
+        let jar = match dsettings.cookies.dir() {
+            Some(dir) => Some(Arc::new(CookieJar::open(&dir)?)),
+            None => None,
+        };
+        let store = match &jar {
+            Some(jar) => jar.load()?,
+            None => CookieStore::default(),
+        };
+        let cookies = Arc::new(CookieStoreMutex::new(store));
+        let client = client_builder(dsettings, &cookies)?.build()?;
+        Ok(Self::from_parts(client, cookies, jar, dsettings))
     }
-}
 
-impl Session {
-    pub fn new() -> Self {
+    // Saves every request and its response to the folder, with the credentials removed
+    pub fn recording(dsettings: &DownloadSettings, dir: &Path) -> Result<Self> {
+        let session = Self::new(dsettings)?;
+        Ok(Self {
+            transport: Transport::Record(Arc::new(Recorder::new(dir, dsettings)?)),
+            ..session
+        })
+    }
+
+    // Answers every request from the fixtures in the folder, nothing is sent
+    pub fn replaying(dsettings: &DownloadSettings, dir: &Path) -> Result<Self> {
+        let cookies = Arc::new(CookieStoreMutex::default());
+        let client = client_builder(dsettings, &cookies)?.build()?;
+        Ok(Self {
+            transport: Transport::Replay(Arc::new(Replayer::new(dir, dsettings)?)),
+            ..Self::from_parts(client, cookies, None, dsettings)
+        })
+    }
+
+    // Folders can override the credentials, their sites must log in on their own.
+    // Every set of credentials gets its own cookies and login locks.
+    pub fn for_credentials(&self, dsettings: &DownloadSettings) -> Result<Self> {
+        let credentials = Credentials::new(dsettings);
+        if credentials == self.credentials {
+            return Ok(self.clone());
+        }
+        for (value, placeholder) in [
+            (&credentials.password, "<password>"),
+            (&credentials.username, "<username>"),
+        ] {
+            if let Some(value) = value {
+                self.add_secret(value, placeholder);
+            }
+        }
+        let mut sessions = self.accounts.sessions.lock().unwrap();
+        let account = match sessions.entry(credentials.clone()) {
+            Entry::Occupied(entry) => entry.get().clone(),
+            Entry::Vacant(entry) => {
+                let jar = match &self.accounts.jar {
+                    Some(jar) => Some(Arc::new(jar.account(&credentials.id())?)),
+                    None => None,
+                };
+                let store = match &jar {
+                    Some(jar) => jar.load()?,
+                    None => CookieStore::default(),
+                };
+                let cookies = Arc::new(CookieStoreMutex::new(store));
+                let client = client_builder(dsettings, &cookies)?.build()?;
+                entry
+                    .insert(Account {
+                        client,
+                        cookies,
+                        jar,
+                        login_mutex: Arc::new(LoginLocks::default()),
+                    })
+                    .clone()
+            }
+        };
+        drop(sessions);
+        Ok(Self {
+            client: account.client,
+            cookies: account.cookies,
+            jar: account.jar,
+            login_mutex: account.login_mutex,
+            credentials,
+            ..self.clone()
+        })
+    }
+
+    // Secrets a module uses besides the credentials of the settings,
+    // they are replaced by the placeholder in recorded fixtures
+    pub fn add_secret(&self, value: &str, placeholder: &'static str) {
+        match &self.transport {
+            Transport::Network => (),
+            Transport::Record(recorder) => recorder.add_secret(value, placeholder),
+            Transport::Replay(replayer) => replayer.add_secret(value, placeholder),
+        }
+    }
+
+    // Starts without cookies and never persists them
+    pub fn without_cookies(&self, dsettings: &DownloadSettings) -> Result<Self> {
+        let cookies = Arc::new(CookieStoreMutex::default());
+        let client = client_builder(dsettings, &cookies)?.build()?;
+        Ok(self.with_client(client, cookies))
+    }
+
+    // Accepts every certificate, only for sites that are explicitly marked as insecure.
+    // Its cookies are never persisted.
+    pub fn insecure(&self, dsettings: &DownloadSettings) -> Result<Self> {
+        let cookies = Arc::new(CookieStoreMutex::default());
+        let client = client_builder(dsettings, &cookies)?
+            .danger_accept_invalid_certs(true)
+            .build()?;
+        Ok(self.with_client(client, cookies))
+    }
+
+    // keeps the headers, rate limits and recording of the session
+    fn with_client(&self, client: Client, cookies: Arc<CookieStoreMutex>) -> Self {
+        Self {
+            client,
+            cookies,
+            jar: None,
+            login_mutex: Arc::new(LoginLocks::default()),
+            ..self.clone()
+        }
+    }
+
+    fn from_parts(
+        client: Client,
+        cookies: Arc<CookieStoreMutex>,
+        jar: Option<Arc<CookieJar>>,
+        dsettings: &DownloadSettings,
+    ) -> Self {
         Self {
-            client: ClientBuilder::new()
-                .cookie_store(true)
-                .connect_timeout(Duration::from_secs(10))
-                .build()
-                .unwrap(),
+            client,
+            cookies,
+            jar: jar.clone(),
+            headers: Arc::new(HeaderMap::new()),
+            limiter: RateLimiter::new(dsettings.rate_limits.clone()),
+            default_limit: None,
+            waited: Arc::default(),
+            retry_delay: Duration::from_millis(dsettings.retry_delay),
+            transport: Transport::Network,
             login_mutex: Arc::new(LoginLocks::default()),
+            credentials: Credentials::new(dsettings),
+            accounts: Arc::new(Accounts {
+                jar,
+                ..Accounts::default()
+            }),
         }
     }
 
+    // Called after every successful login, so the next run can reuse it
+    pub async fn save_cookies(&self) -> Result<()> {
+        let jar = match &self.jar {
+            Some(jar) => jar,
+            None => return Ok(()),
+        };
+        jar.save_from(&self.cookies).await
+    }
+
+    // The headers are added to every request of the returned session,
+    // headers set on a request still take precedence
+    pub fn with_headers(&self, headers: HeaderMap) -> Self {
+        let mut merged = (*self.headers).clone();
+        merged.extend(headers);
+        Self {
+            headers: Arc::new(merged),
+            ..self.clone()
+        }
+    }
+
+    // The returned session counts its waiting time separately
+    pub fn with_rate_limit(&self, default_limit: Option<RateLimit>) -> Self {
+        Self {
+            default_limit,
+            waited: Arc::default(),
+            ..self.clone()
+        }
+    }
+
+    pub fn waited(&self) -> Duration {
+        Duration::from_millis(self.waited.load(Ordering::Relaxed))
+    }
+
     pub fn get<U: IntoUrl>(&self, url: U) -> SRequestBuilder {
         self.request(Method::GET, url)
     }
@@ -48,42 +288,143 @@ impl Session {
     }
 
     pub fn request<U: IntoUrl>(&self, method: Method, url: U) -> SRequestBuilder {
+        let mut inner = self.client.request(method, url);
+        if !self.headers.is_empty() {
+            inner = inner.headers((*self.headers).clone());
+        }
         SRequestBuilder {
-            inner: self.client.request(method, url),
+            inner,
             session: self.clone(),
         }
     }
 
+    #[instrument(
+        level = "debug",
+        skip_all,
+        fields(method = %request.method(), url = %request.url())
+    )]
     pub async fn execute(&self, request: Request) -> Result<Response> {
+        let url = request.url().clone();
         let cloneable = request
             .body()
             .map(|b| b.as_bytes().is_some())
             .unwrap_or(true);
-        if cloneable {
+        let result = if let Transport::Replay(replayer) = &self.transport {
+            replayer.respond(&request)
+        } else if cloneable {
             self.retry_execute(request).await
         } else {
             self._execute(request).await
-        }
+        };
+        result.with_context(|| ErrorContext::Url(url.to_string()))
     }
 
     // request must be cloneable
     async fn retry_execute(&self, request: Request) -> Result<Response> {
+        // a POST like a login form may have been handled even though the server failed
+        // or the connection broke, so it is sent only once
+        let idempotent = is_idempotent(request.method());
         for i in 0..4 {
             match self._execute(request.try_clone().unwrap()).await {
+                Ok(response) if idempotent && is_retryable_status(response.status()) => {
+                    warn!(attempt = i, status = %response.status(), "Request failed, retrying");
+                }
                 Ok(response) => return Ok(response),
-                Err(err) => {
-                    println!("{}, {:?}", i, err)
+                Err(err) if idempotent && err.is_retryable() => {
+                    warn!(attempt = i, error = %err, "Request failed, retrying");
                 }
+                Err(err) => return Err(err),
             }
-            tokio::time::sleep(Duration::from_secs(3)).await;
+            metrics().record_retry(request.url().host_str().unwrap_or_default());
+            tokio::time::sleep(self.retry_delay).await;
         }
 
         self._execute(request).await
     }
 
     async fn _execute(&self, request: Request) -> Result<Response> {
-        Ok(tokio::time::timeout(Duration::from_secs(30), self.client.execute(request)).await??)
+        if let Some(host) = request.url().host_str() {
+            let waited = self.limiter.wait(host, self.default_limit).await;
+            if !waited.is_zero() {
+                debug!(?waited, host, "Held back by the rate limit");
+            }
+            self.waited
+                .fetch_add(waited.as_millis() as u64, Ordering::Relaxed);
+        }
+        let key = match &self.transport {
+            Transport::Record(recorder) => Some(recorder.request_key(&request)),
+            _ => None,
+        };
+        let host = request.url().host_str().unwrap_or_default().to_owned();
+        let started = Instant::now();
+        let result =
+            tokio::time::timeout(Duration::from_secs(30), self.client.execute(request)).await;
+        let status = match &result {
+            Ok(Ok(response)) => Some(response.status().as_u16()),
+            _ => None,
+        };
+        metrics().record_request(&host, status, started.elapsed());
+        let response = result??;
+        debug!(status = %response.status(), "Got response");
+        match (&self.transport, key) {
+            (Transport::Record(recorder), Some(key)) => recorder.record(key, response).await,
+            _ => Ok(response),
+        }
+    }
+}
+
+fn client_builder(
+    dsettings: &DownloadSettings,
+    cookies: &Arc<CookieStoreMutex>,
+) -> Result<ClientBuilder> {
+    // the configured headers replace the default user agent
+    let builder = ClientBuilder::new()
+        .cookie_provider(Arc::clone(cookies))
+        .user_agent(DEFAULT_USER_AGENT)
+        .default_headers(header_map(&dsettings.headers)?)
+        .connect_timeout(Duration::from_secs(10));
+    with_tls(with_proxies(builder, &dsettings.proxy)?, &dsettings.tls)
+}
+
+pub fn header_map(headers: &HashMap<String, String>) -> Result<HeaderMap> {
+    let mut map = HeaderMap::with_capacity(headers.len());
+    for (name, value) in headers {
+        let invalid = || TErrorKind::InvalidHeader(name.clone());
+        map.insert(
+            HeaderName::from_bytes(name.trim().as_bytes()).map_err(|_| invalid())?,
+            HeaderValue::from_str(value.trim()).map_err(|_| invalid())?,
+        );
+    }
+    Ok(map)
+}
+
+fn with_tls(mut builder: ClientBuilder, settings: &TlsSettings) -> Result<ClientBuilder> {
+    for path in &settings.root_certificates {
+        let pem = std::fs::read(path.as_path())?;
+        builder = builder.add_root_certificate(Certificate::from_pem(&pem)?);
+    }
+    if let Some(identity) = &settings.client_identity {
+        let der = std::fs::read(identity.pkcs12.as_path())?;
+        let password = identity.password.as_deref().unwrap_or_default();
+        builder = builder.identity(Identity::from_pkcs12_der(&der, password)?);
+    }
+    Ok(builder)
+}
+
+// reqwest only reads the proxy environment variables itself if no proxy is added,
+// so they are resolved by ProxySettings instead
+fn with_proxies(mut builder: ClientBuilder, settings: &ProxySettings) -> Result<ClientBuilder> {
+    builder = builder.no_proxy();
+    let no_proxy = settings.no_proxy();
+    let proxies = [
+        settings.http().map(Proxy::http).transpose()?,
+        settings.https().map(Proxy::https).transpose()?,
+        settings.socks5().map(Proxy::all).transpose()?,
+    ];
+    for proxy in proxies.into_iter().flatten() {
+        builder = builder.proxy(proxy.no_proxy(no_proxy.as_deref().and_then(NoProxy::from_string)));
     }
+    Ok(builder)
 }
 
 impl SRequestBuilder {
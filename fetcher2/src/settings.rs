@@ -1,10 +1,14 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
 use serde::{Deserialize, Serialize};
 
-use config::ctypes::path::{Absolute, StrictPath};
+use config::ctypes::path::{Absolute, AbsoluteExistFile, StrictPath};
 use config::traveller::Travel;
 
 use crate::error::{Result, TErrorKind};
-use crate::template::DownloadArgs;
+use crate::notifier::WebhookSettings;
+use crate::template::{DownloadArgs, Extensions};
 
 #[cfg_attr(feature = "druid", derive(druid::Data))]
 #[derive(Travel, Serialize, Deserialize, Debug, Clone)]
@@ -24,6 +28,60 @@ pub struct DownloadSettings {
     #[travel(default = false)]
     #[travel(name = "Force Download")]
     pub force: bool,
+
+    #[serde(default = "concurrency_default")]
+    #[travel(default = 512, name = "Concurrent Downloads per Site")]
+    pub concurrency: u64,
+
+    /// Failed requests are sent again after this many milliseconds, up to 4 times
+    #[serde(default = "retry_delay_default")]
+    #[travel(default = 3000, name = "Retry Delay in ms")]
+    pub retry_delay: u64,
+
+    #[serde(default)]
+    #[travel(name = "History")]
+    pub history: HistoryRetention,
+
+    #[serde(default)]
+    #[travel(name = "Webhook")]
+    pub webhook: Option<WebhookSettings>,
+
+    #[serde(default)]
+    #[travel(name = "Proxy")]
+    pub proxy: ProxySettings,
+
+    #[serde(default)]
+    #[travel(name = "TLS")]
+    pub tls: TlsSettings,
+
+    #[serde(default)]
+    #[travel(name = "Cookies")]
+    pub cookies: CookieSettings,
+
+    /// Sent with every request, e.g. User-Agent, Accept-Language or an API token
+    #[serde(default)]
+    #[travel(name = "Headers")]
+    #[cfg_attr(feature = "druid", data(same_fn = "PartialEq::eq"))]
+    pub headers: HashMap<String, String>,
+
+    /// Limits for a domain and its subdomains, e.g. moodle-app2.let.ethz.ch.
+    /// Replaces the limits the modules use by default.
+    #[serde(default)]
+    #[travel(name = "Rate Limits")]
+    #[cfg_attr(feature = "druid", data(same_fn = "PartialEq::eq"))]
+    pub rate_limits: HashMap<String, RateLimit>,
+
+    #[serde(default)]
+    #[travel(name = "Metrics")]
+    pub metrics: MetricsSettings,
+}
+
+fn concurrency_default() -> u64 {
+    512
+}
+
+fn retry_delay_default() -> u64 {
+    3000
 }
 
 impl DownloadSettings {
@@ -37,4 +95,275 @@ impl DownloadSettings {
             .as_ref()
             .ok_or_else(|| TErrorKind::LoginDataRequired.into())
     }
+
+    pub fn max_concurrent(&self) -> usize {
+        self.concurrency.max(1) as usize
+    }
+}
+
+#[cfg_attr(feature = "druid", derive(druid::Data))]
+#[derive(Travel, Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct HistoryRetention {
+    /// Older entries are removed after each run
+    #[serde(default)]
+    #[travel(name = "Max Entries per Site")]
+    pub max_entries: Option<u64>,
+
+    #[serde(default)]
+    #[travel(name = "Max Age in Days")]
+    pub max_days: Option<u64>,
+}
+
+#[cfg_attr(feature = "druid", derive(druid::Data))]
+#[derive(Travel, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ProxySettings {
+    /// Used for http urls, e.g. http://proxy.example.com:8080
+    #[serde(default)]
+    #[travel(name = "HTTP Proxy")]
+    pub http: Option<String>,
+
+    /// Used for https urls
+    #[serde(default)]
+    #[travel(name = "HTTPS Proxy")]
+    pub https: Option<String>,
+
+    /// Used for all urls without a matching HTTP(S) proxy, e.g. socks5://localhost:1080
+    #[serde(default)]
+    #[travel(name = "SOCKS5 Proxy")]
+    pub socks5: Option<String>,
+
+    /// Comma separated hosts and domains that are never proxied, e.g. localhost,.ethz.ch
+    #[serde(default)]
+    #[travel(name = "No Proxy")]
+    pub no_proxy: Option<String>,
+
+    /// Unset fields are taken from HTTP_PROXY, HTTPS_PROXY, ALL_PROXY and NO_PROXY
+    #[serde(default = "use_env_default")]
+    #[travel(default = true, name = "Use Environment Variables")]
+    pub use_env: bool,
+}
+
+fn use_env_default() -> bool {
+    true
+}
+
+impl Default for ProxySettings {
+    fn default() -> Self {
+        Self {
+            http: None,
+            https: None,
+            socks5: None,
+            no_proxy: None,
+            use_env: use_env_default(),
+        }
+    }
+}
+
+impl ProxySettings {
+    pub fn http(&self) -> Option<String> {
+        self.or_env(&self.http, "HTTP_PROXY")
+    }
+
+    pub fn https(&self) -> Option<String> {
+        self.or_env(&self.https, "HTTPS_PROXY")
+    }
+
+    pub fn socks5(&self) -> Option<String> {
+        self.or_env(&self.socks5, "ALL_PROXY")
+    }
+
+    pub fn no_proxy(&self) -> Option<String> {
+        self.or_env(&self.no_proxy, "NO_PROXY")
+    }
+
+    fn or_env(&self, value: &Option<String>, var: &str) -> Option<String> {
+        let value = match value {
+            Some(value) => Some(value.clone()),
+            None if self.use_env => std::env::var(var)
+                .or_else(|_| std::env::var(var.to_lowercase()))
+                .ok(),
+            None => None,
+        };
+        value.filter(|value| !value.trim().is_empty())
+    }
+}
+
+#[cfg_attr(feature = "druid", derive(druid::Data))]
+#[derive(Travel, Serialize, Deserialize, Debug, Clone, Default)]
+pub struct TlsSettings {
+    /// PEM files with additional trusted root certificates, e.g. a self-signed corporate CA
+    #[serde(default)]
+    #[travel(name = "Extra Root Certificates")]
+    #[cfg_attr(feature = "druid", data(same_fn = "same_paths"))]
+    pub root_certificates: Vec<StrictPath<AbsoluteExistFile>>,
+
+    #[serde(default)]
+    #[travel(name = "Client Certificate")]
+    pub client_identity: Option<ClientIdentity>,
+}
+
+#[cfg_attr(feature = "druid", derive(druid::Data))]
+#[derive(Travel, Serialize, Deserialize, Debug, Clone)]
+pub struct ClientIdentity {
+    /// PKCS#12 file (.p12 or .pfx) with the certificate and its private key
+    #[travel(name = "PKCS#12 File")]
+    pub pkcs12: StrictPath<AbsoluteExistFile>,
+
+    #[serde(default)]
+    #[travel(name = "Password")]
+    pub password: Option<String>,
+}
+
+#[cfg_attr(feature = "druid", derive(druid::Data))]
+#[derive(Travel, Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    /// 0 disables the limit
+    #[travel(name = "Requests per Second")]
+    pub per_second: f64,
+
+    /// Requests that may be sent at once after a pause
+    #[serde(default = "burst_default")]
+    #[travel(default = 1, name = "Burst")]
+    pub burst: u64,
+}
+
+fn burst_default() -> u64 {
+    1
+}
+
+impl RateLimit {
+    pub fn new(per_second: f64, burst: u64) -> Self {
+        Self { per_second, burst }
+    }
+
+    pub fn capacity(&self) -> f64 {
+        self.burst.max(1) as f64
+    }
+}
+
+#[cfg_attr(feature = "druid", derive(druid::Data))]
+#[derive(Travel, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CookieSettings {
+    /// Keeps the logins between runs, the cookies are stored encrypted
+    #[serde(default = "persist_default")]
+    #[travel(default = true, name = "Remember Logins")]
+    pub persist: bool,
+
+    /// Defaults to the data folder of fetcher2
+    #[serde(default)]
+    #[travel(name = "Cookie Folder")]
+    pub dir: Option<StrictPath<Absolute>>,
+}
+
+fn persist_default() -> bool {
+    true
+}
+
+impl Default for CookieSettings {
+    fn default() -> Self {
+        Self {
+            persist: persist_default(),
+            dir: None,
+        }
+    }
+}
+
+impl CookieSettings {
+    pub fn dir(&self) -> Option<PathBuf> {
+        if !self.persist {
+            return None;
+        }
+        match &self.dir {
+            Some(dir) => Some(dir.as_path().to_owned()),
+            None => directories::ProjectDirs::from("ch", "fetcher2", "fetcher2")
+                .map(|dirs| dirs.data_dir().join("cookies")),
+        }
+    }
+}
+
+#[cfg_attr(feature = "druid", derive(druid::Data))]
+#[derive(Travel, Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct MetricsSettings {
+    /// Written after every run in the Prometheus text format,
+    /// e.g. into the textfile collector folder of node_exporter
+    #[serde(default)]
+    #[travel(name = "Textfile")]
+    pub textfile: Option<StrictPath<Absolute>>,
+
+    /// Serves the metrics on 127.0.0.1 while the program is running
+    #[serde(default)]
+    #[travel(name = "Port")]
+    pub port: Option<u64>,
+}
+
+impl MetricsSettings {
+    pub fn port(&self) -> Result<Option<u16>> {
+        self.port
+            .map(|port| u16::try_from(port).map_err(|_| TErrorKind::InvalidPort(port).into()))
+            .transpose()
+    }
+}
+
+#[cfg(feature = "druid")]
+fn same_paths(a: &[StrictPath<AbsoluteExistFile>], b: &[StrictPath<AbsoluteExistFile>]) -> bool {
+    use druid::Data;
+    a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.same(b))
+}
+
+// every set field replaces the inherited value for all descendants of a folder
+#[cfg_attr(feature = "druid", derive(druid::Data))]
+#[derive(Travel, Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct SettingsOverrides {
+    #[serde(default)]
+    #[travel(name = "Username")]
+    pub username: Option<String>,
+
+    #[serde(default)]
+    #[travel(name = "Password")]
+    pub password: Option<String>,
+
+    #[serde(default)]
+    #[travel(name = "Save Path")]
+    pub save_path: Option<StrictPath<Absolute>>,
+
+    #[serde(default)]
+    #[travel(name = "Module Setting")]
+    pub download_args: Option<DownloadArgs>,
+
+    #[serde(default)]
+    #[travel(name = "Extension Filter")]
+    pub extensions: Option<Extensions>,
+
+    #[serde(default)]
+    #[travel(name = "Concurrent Downloads per Site")]
+    pub concurrency: Option<u64>,
+}
+
+impl SettingsOverrides {
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+
+    pub fn apply(&self, dsettings: &DownloadSettings) -> DownloadSettings {
+        let mut dsettings = dsettings.clone();
+        if let Some(username) = &self.username {
+            dsettings.username = Some(username.clone());
+        }
+        if let Some(password) = &self.password {
+            dsettings.password = Some(password.clone());
+        }
+        if let Some(save_path) = &self.save_path {
+            dsettings.save_path = save_path.clone();
+        }
+        if let Some(download_args) = &self.download_args {
+            dsettings.download_args = download_args.clone();
+        }
+        if let Some(extensions) = &self.extensions {
+            dsettings.download_args.extensions = extensions.clone();
+        }
+        if let Some(concurrency) = self.concurrency {
+            dsettings.concurrency = concurrency;
+        }
+        dsettings
+    }
 }
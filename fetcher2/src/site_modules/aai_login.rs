@@ -42,7 +42,9 @@ pub async fn aai_login<T: Serialize + ?Sized>(
     let text = session.post(url).form(form).send().await?.text().await?;
 
     let sam_text = if !text.contains("SAMLResponse") {
-        let local_storage_part = &ACTION_URL_RE.captures(&text).wrong_format()?[1];
+        let local_storage_part = &ACTION_URL_RE
+            .captures(&text)
+            .wrong_format("local storage form")?[1];
         let local_storage_url = Url::parse(BASE_URL).unwrap().join(local_storage_part)?;
         let login_page = session
             .post(local_storage_url)
@@ -57,7 +59,9 @@ pub async fn aai_login<T: Serialize + ?Sized>(
             ("j_username", dsettings.try_username()?),
             ("j_password", dsettings.try_password()?),
         ];
-        let sso_part = &ACTION_URL_RE.captures(&login_page).wrong_format()?[1];
+        let sso_part = &ACTION_URL_RE
+            .captures(&login_page)
+            .wrong_format("login form")?[1];
         let sso_url = Url::parse(BASE_URL).unwrap().join(sso_part)?;
         session
             .post(sso_url)
@@ -71,10 +75,20 @@ pub async fn aai_login<T: Serialize + ?Sized>(
     };
 
     let sam_url = Url::parse(&unescape(
-        &ACTION_URL_RE.captures(&sam_text).wrong_format()?[1],
+        &ACTION_URL_RE
+            .captures(&sam_text)
+            .wrong_format("saml form")?[1],
     ))?;
-    let ssm = unescape(&RELAY_STATE_RE.captures(&sam_text).wrong_format()?[1]);
-    let sam = unescape(&SAMLRESPONSE_RE.captures(&sam_text).wrong_format()?[1]);
+    let ssm = unescape(
+        &RELAY_STATE_RE
+            .captures(&sam_text)
+            .wrong_format("relay state")?[1],
+    );
+    let sam = unescape(
+        &SAMLRESPONSE_RE
+            .captures(&sam_text)
+            .wrong_format("saml response")?[1],
+    );
 
     let saml_form = [("RelayState", &ssm), ("SAMLResponse", &sam)];
 
@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::Sender;
+use url::Url;
+
+use config::traveller::Travel;
+
+use crate::error::{ErrorContext, Result, TError, TErrorContext, TErrorKind};
+use crate::session::{header_map, Session};
+use crate::settings::DownloadSettings;
+use crate::site_modules::module::ModuleExt;
+use crate::site_modules::utils::save_path;
+use crate::task::{Task, TaskBuilder};
+
+// Downloads the files of a json index, so the tests can run whole templates against a
+// local server: [{"path": "slides/week1.pdf", "url": "files/week1.pdf", "checksum": "..."}]
+// An entry may also have "headers", which are sent with the download of its file.
+// With a username the credentials are posted to "login" next to the index first.
+// Only built with the test-support feature, users never get to choose it.
+#[cfg_attr(feature = "druid", derive(druid::Data, druid::Lens))]
+#[derive(Travel, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Listing {
+    /// Url of the json index, relative file urls are resolved against it
+    #[travel(name = "Index Url")]
+    pub url: String,
+
+    #[travel(name = "Folder Name")]
+    pub name: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct ListingEntry {
+    path: String,
+    url: String,
+    #[serde(default)]
+    checksum: Option<String>,
+    // without an extension it is taken from the response headers
+    #[serde(default = "has_extension_default")]
+    has_extension: bool,
+    #[serde(default)]
+    headers: HashMap<String, String>,
+}
+
+fn has_extension_default() -> bool {
+    true
+}
+
+#[async_trait]
+impl ModuleExt for Listing {
+    async fn fetch_urls_impl(
+        &self,
+        session: Session,
+        sender: Sender<Task>,
+        _dsettings: Arc<DownloadSettings>,
+    ) -> Result<()> {
+        let index_url = Url::parse(&self.url)?;
+        let entries: Vec<ListingEntry> = session
+            .get(index_url.clone())
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+            .context(ErrorContext::Parse("index"))?;
+
+        for entry in entries {
+            let mut builder =
+                TaskBuilder::new(relative_path(&entry.path)?, index_url.join(&entry.url)?)
+                    .extension(entry.has_extension);
+            if let Some(checksum) = entry.checksum {
+                builder = builder.checksum(checksum);
+            }
+            if !entry.headers.is_empty() {
+                builder = builder.headers(header_map(&entry.headers)?);
+            }
+            sender.send(builder.build()).await.unwrap();
+        }
+        Ok(())
+    }
+
+    async fn login_impl(&self, session: &Session, dsettings: &DownloadSettings) -> Result<()> {
+        let username = match &dsettings.username {
+            Some(username) => username,
+            None => return Ok(()),
+        };
+        let form = [
+            ("username", username.as_str()),
+            (
+                "password",
+                dsettings.password.as_deref().unwrap_or_default(),
+            ),
+        ];
+        session
+            .post(Url::parse(&self.url)?.join("login")?)
+            .form(&form)
+            .send()
+            .await?
+            .error_for_status()
+            .map_err(|_| TErrorKind::LoginError)?;
+        Ok(())
+    }
+
+    fn website_url_impl(&self) -> String {
+        self.url.clone()
+    }
+
+    async fn folder_name_impl(
+        &self,
+        _session: &Session,
+        _dsettings: &DownloadSettings,
+    ) -> Result<PathBuf> {
+        Ok(PathBuf::from(save_path(&self.name)?))
+    }
+}
+
+// the index must not write outside of the folder of the site
+fn relative_path(path: &str) -> Result<PathBuf> {
+    Path::new(path)
+        .components()
+        .map(|component| match component {
+            Component::Normal(part) => save_path(&part.to_string_lossy()),
+            _ => Err(TError::new(TErrorKind::WrongFormat).context(ErrorContext::Path(path.into()))),
+        })
+        .collect()
+}
@@ -5,6 +5,7 @@ use std::time::Duration;
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 use tokio::sync::mpsc::Sender;
+use tracing::debug;
 use url::Url;
 
 use config::traveller::Travel;
@@ -30,7 +31,6 @@ impl ModuleExt for Minimal {
         sender: Sender<Task>,
         _dsettings: Arc<DownloadSettings>,
     ) -> Result<()> {
-        println!("Retirevinbg Urls");
         //tokio::time::sleep(Duration::from_secs(3)).await;
         let task = TaskBuilder::new(
             PathBuf::from("hello.hello"),
@@ -39,12 +39,12 @@ impl ModuleExt for Minimal {
         .build();
         sender.send(task).await.unwrap();
         let resp = session.get("https://www.google.com/").send().await?;
-        println!("1{}", resp.status());
+        debug!(status = %resp.status(), "First request");
         let resp = session.get("https://www.google.com/").send().await?;
-        println!("2{}", resp.status());
+        debug!(status = %resp.status(), "Second request");
         tokio::time::sleep(Duration::from_secs(3)).await;
         let resp = session.get("https://www.google.com/").send().await?;
-        println!("3{}", resp.status());
+        debug!(status = %resp.status(), "Delayed request");
         let task = TaskBuilder::new(
             PathBuf::from("hello2.hello"),
             Url::parse("https://www.google.com/").unwrap(),
@@ -80,7 +80,6 @@ impl ModuleExt for Minimal {
     }
 
     async fn login_impl(&self, session: &Session, dsettings: &DownloadSettings) -> Result<()> {
-        println!("LOGIN MINIMAL");
         let url =
             url::Url::parse("https://moodle-app2.let.ethz.ch/auth/shibboleth/login.php").unwrap();
 
@@ -99,7 +98,6 @@ impl ModuleExt for Minimal {
         _session: &Session,
         _dsettings: &DownloadSettings,
     ) -> Result<PathBuf> {
-        println!("Folder Name");
         tokio::time::sleep(Duration::from_secs(5)).await;
         Ok(PathBuf::from("efgeuif"))
     }
@@ -1,8 +1,12 @@
+#[cfg(feature = "test-support")]
+pub use crate::site_modules::listing::*;
 pub use crate::site_modules::minimal::*;
 pub use crate::site_modules::module::*;
 pub use crate::site_modules::polybox::*;
 
 mod aai_login;
+#[cfg(feature = "test-support")]
+mod listing;
 mod minimal;
 mod module;
 mod moodle;
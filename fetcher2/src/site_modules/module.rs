@@ -9,13 +9,16 @@ use serde::Serialize;
 use strum_macros::Display;
 use tokio::sync::mpsc::Sender;
 use tokio::sync::{Mutex, MutexGuard};
+use tracing::warn;
 
 use config::traveller::Travel;
 use fetcher2_macro::{login_locks, LoginLock};
 
-use crate::error::{Result, TErrorKind};
+use crate::error::{ErrorContext, Result, TError, TErrorContext, TErrorKind};
 use crate::session::Session;
-use crate::settings::DownloadSettings;
+use crate::settings::{DownloadSettings, RateLimit};
+#[cfg(feature = "test-support")]
+use crate::site_modules::listing::Listing;
 use crate::site_modules::minimal::Minimal;
 use crate::site_modules::moodle::Moodle;
 use crate::site_modules::polybox::Polybox;
@@ -31,6 +34,10 @@ pub enum Module {
     Polybox(Polybox),
 
     Moodle(Moodle),
+
+    // only for the tests, see site_modules::listing
+    #[cfg(feature = "test-support")]
+    Listing(Listing),
 }
 
 impl Module {
@@ -38,15 +45,26 @@ impl Module {
         let mut lock = self.get_lock(&session.login_mutex).await;
         match &*lock {
             LoginState::Success => Ok(()),
-            LoginState::Failure => Err(TErrorKind::PreviousLoginError.into()),
+            LoginState::Failure => Err(TError::new(TErrorKind::PreviousLoginError)
+                .context(ErrorContext::Module(self.name()))),
             LoginState::Uninitiated => {
+                // the cookies of a previous run may still be logged in
+                if let Ok(true) = self.session_valid_impl(session, dsettings).await {
+                    *lock = LoginState::Success;
+                    return Ok(());
+                }
                 let r = self.login_impl(session, dsettings).await;
                 *lock = if r.is_ok() {
                     LoginState::Success
                 } else {
                     LoginState::Failure
                 };
-                r
+                r.context(ErrorContext::Operation("logging in"))
+                    .with_context(|| ErrorContext::Module(self.name()))?;
+                if let Err(err) = session.save_cookies().await {
+                    warn!(error = %err, "Could not save the cookies");
+                }
+                Ok(())
             }
         }
     }
@@ -57,7 +75,10 @@ impl Module {
         sender: Sender<Task>,
         dsettings: Arc<DownloadSettings>,
     ) -> Result<()> {
-        self.fetch_urls_impl(session, sender, dsettings).await
+        self.fetch_urls_impl(session, sender, dsettings)
+            .await
+            .context(ErrorContext::Operation("fetching the urls"))
+            .with_context(|| ErrorContext::Module(self.name()))
     }
 
     pub async fn folder_name(
@@ -65,12 +86,23 @@ impl Module {
         session: &Session,
         dsettings: &DownloadSettings,
     ) -> Result<PathBuf> {
-        self.folder_name_impl(session, dsettings).await
+        self.folder_name_impl(session, dsettings)
+            .await
+            .context(ErrorContext::Operation("getting the folder name"))
+            .with_context(|| ErrorContext::Module(self.name()))
     }
 
     pub fn name(&self) -> String {
         self.to_string()
     }
+
+    pub fn rate_limit(&self) -> Option<RateLimit> {
+        self.rate_limit_impl()
+    }
+
+    pub fn user_agent(&self) -> Option<&'static str> {
+        self.user_agent_impl()
+    }
 }
 
 #[login_locks]
@@ -111,8 +143,27 @@ pub trait ModuleExt {
         Ok(())
     }
 
+    // Modules that can't tell always log in again
+    async fn session_valid_impl(
+        &self,
+        _session: &Session,
+        _dsettings: &DownloadSettings,
+    ) -> Result<bool> {
+        Ok(false)
+    }
+
     fn website_url_impl(&self) -> String;
 
+    // used for every host the settings have no limit for
+    fn rate_limit_impl(&self) -> Option<RateLimit> {
+        None
+    }
+
+    // replaces the default user agent, a configured one still takes precedence
+    fn user_agent_impl(&self) -> Option<&'static str> {
+        None
+    }
+
     async fn folder_name_impl(
         &self,
         session: &Session,
@@ -13,7 +13,7 @@ use config::traveller::Travel;
 
 use crate::error::TErrorFast;
 use crate::session::Session;
-use crate::settings::DownloadSettings;
+use crate::settings::{DownloadSettings, RateLimit};
 use crate::site_modules::aai_login::aai_login;
 use crate::site_modules::module::ModuleExt;
 use crate::site_modules::utils::remove_vz_id;
@@ -62,10 +62,27 @@ impl ModuleExt for Moodle {
         aai_login(session, dsettings, LOGIN_URL.clone(), &LOGIN_FORM).await
     }
 
+    // moodle redirects to its login page if the cookies have expired
+    async fn session_valid_impl(
+        &self,
+        session: &Session,
+        _dsettings: &DownloadSettings,
+    ) -> Result<bool> {
+        let mut url = COURSE_URL.clone();
+        url.set_query(Some(&format!("id={}", self.id)));
+        let response = session.get(url).send().await?;
+        Ok(response.status().is_success() && !response.url().path().starts_with("/login/"))
+    }
+
     fn website_url_impl(&self) -> String {
         todo!()
     }
 
+    // crawling the course pages quickly trips the abuse protection
+    fn rate_limit_impl(&self) -> Option<RateLimit> {
+        Some(RateLimit::new(2., 4))
+    }
+
     async fn folder_name_impl(
         &self,
         session: &Session,
@@ -81,7 +98,7 @@ impl ModuleExt for Moodle {
             .tag("div")
             .class("page-header-headings")
             .find()
-            .wrong_format()?
+            .wrong_format("course name")?
             .text();
         Ok(PathBuf::from(remove_vz_id(&name).as_ref()))
     }
@@ -40,18 +40,12 @@ lazy_static! {
     static ref WEBDAV_REMOTE_URL: Url =
         Url::parse("https://polybox.ethz.ch/remote.php/webdav/").unwrap();
     static ref HEADERS: HeaderMap = {
-        let mut m = HeaderMap::with_capacity(3);
+        let mut m = HeaderMap::with_capacity(2);
         m.insert(
             "Content-Type",
             HeaderValue::from_static("application/xml; charset=utf-8"),
         );
         m.insert("Depth", HeaderValue::from_str("infinity").unwrap());
-        m.insert(
-            "User-Agent",
-            HeaderValue::from_static(
-                "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:74.0) Gecko/20100101 Firefox/74.0",
-            ),
-        );
         m
     };
 }
@@ -73,30 +67,12 @@ pub enum Mode {
 
 impl Polybox {
     pub async fn html_login(&self, session: &Session, password: &str) -> Result<()> {
-        lazy_static! {
-            static ref TOKEN_RE: Regex = Regex::new("<head data-requesttoken=\"(.*)\">").unwrap();
-        }
         let url = INDEX_URL
             .join("s/")
             .unwrap()
             .join(&format!("{}/", self.id))?
             .join("authenticate")?;
-
-        let resp = session.get(url.clone()).send().await?;
-
-        let text = resp.text().await?;
-        let token = &TOKEN_RE.captures(&text).wrong_format()?[1];
-
-        let data = [("requesttoken", token), ("password", password)];
-
-        session
-            .post(url)
-            .form(&data)
-            .send()
-            .await?
-            .error_for_status()?;
-
-        Ok(())
+        share_login(session, url, password).await
     }
 
     pub async fn dire_path(
@@ -130,7 +106,6 @@ impl ModuleExt for Polybox {
     ) -> Result<()> {
         match &self.mode {
             Mode::Shared(password) => {
-                println!("STARTING {:?}", password);
                 let response = session
                     .request(
                         Method::from_str("PROPFIND").unwrap(),
@@ -187,6 +162,11 @@ impl ModuleExt for Polybox {
         "todo!()".to_owned()
     }
 
+    // the webdav api refuses unknown clients
+    fn user_agent_impl(&self) -> Option<&'static str> {
+        Some("Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:74.0) Gecko/20100101 Firefox/74.0")
+    }
+
     async fn folder_name_impl(
         &self,
         session: &Session,
@@ -195,12 +175,12 @@ impl ModuleExt for Polybox {
         match &self.mode {
             Mode::Private => {
                 let dir_path = self.dire_path(session, dsettings).await?;
-                let folder_name = dir_path.split('/').last().wrong_format()?;
+                let folder_name = dir_path.split('/').last().wrong_format("folder path")?;
                 return Ok(PathBuf::from(folder_name));
             }
             Mode::Shared(password) => {
                 // polybox doesn't work without a new session  ¯\_(ツ)_/¯
-                let new_session = Session::new();
+                let new_session = session.without_cookies(dsettings)?;
 
                 if let Some(password) = password {
                     self.html_login(&new_session, password).await?;
@@ -219,20 +199,44 @@ impl ModuleExt for Polybox {
                 let data_node = soup
                     .tag("body")
                     .find()
-                    .wrong_format()?
+                    .wrong_format("share page")?
                     .tag("header")
                     .find()
-                    .wrong_format()?
+                    .wrong_format("share page")?
                     .tag("div")
                     .find()
-                    .wrong_format()?;
-                let name = data_node.get("data-name").wrong_format()?;
+                    .wrong_format("share page")?;
+                let name = data_node.get("data-name").wrong_format("share name")?;
                 Ok(PathBuf::from(name))
             }
         }
     }
 }
 
+// Unlocks a password protected share, url is the authenticate page of the share
+pub async fn share_login(session: &Session, url: Url, password: &str) -> Result<()> {
+    lazy_static! {
+        static ref TOKEN_RE: Regex = Regex::new("<head data-requesttoken=\"(.*)\">").unwrap();
+    }
+    session.add_secret(password, "<share-password>");
+
+    let resp = session.get(url.clone()).send().await?;
+
+    let text = resp.text().await?;
+    let token = &TOKEN_RE.captures(&text).wrong_format("request token")?[1];
+
+    let data = [("requesttoken", token), ("password", password)];
+
+    session
+        .post(url)
+        .form(&data)
+        .send()
+        .await?
+        .error_for_status()?;
+
+    Ok(())
+}
+
 struct XmlReader<'a> {
     reader: Reader<&'a [u8]>,
     buf: Vec<u8>,
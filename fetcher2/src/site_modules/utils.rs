@@ -3,8 +3,7 @@ use std::borrow::Cow;
 use lazy_static::lazy_static;
 use regex::Regex;
 
-use crate::error::TErrorKind;
-use crate::{Result, TError};
+use crate::error::{Result, TErrorFast};
 
 pub fn unescape(str: &str) -> String {
     let mut r = String::new();
@@ -14,7 +13,8 @@ pub fn unescape(str: &str) -> String {
 
 pub fn save_path(part: &str) -> Result<String> {
     let saver_part = urlencoding::decode(&unescape(&part.replace("/", "-").replace("\\", "-")))
-        .map_err(|_| TError::new(TErrorKind::WrongFormat))?;
+        .ok()
+        .wrong_format("file name")?;
     Ok(saver_part
         .trim()
         .replace(":", ";")
@@ -1,6 +1,10 @@
-use tokio::sync::mpsc::Sender;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::mpsc::{self, Receiver, Sender};
 
 use crate::template::nodes::node::{NodeEvent, NodeEventKind};
+use crate::template::report::SharedReport;
 use crate::template::NodeIndex;
 
 pub trait RawCommunicationExt<T: CommunicationExt>: Clone {
@@ -11,20 +15,106 @@ pub trait CommunicationExt: Clone + Send + Sync + 'static {
     fn send_event<T: Into<NodeEventKind>>(&self, event: T);
 }
 
+const SUBSCRIBER_CAPACITY: usize = 1024;
+
+#[derive(Debug, Clone)]
+struct Subscriber {
+    tx: Sender<NodeEvent>,
+    // progress events are dropped instead of waiting for a full channel
+    lossy: bool,
+}
+
+// Every subscriber gets every event, closed subscribers are removed
+#[derive(Clone, Default)]
+pub struct EventBus {
+    subscribers: Arc<Mutex<Vec<Subscriber>>>,
+}
+
+impl fmt::Debug for EventBus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EventBus")
+            .field("subscribers", &self.subscribers.lock().unwrap().len())
+            .finish()
+    }
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn subscribe(&self) -> Receiver<NodeEvent> {
+        self.add_subscriber(false)
+    }
+
+    pub fn subscribe_lossy(&self) -> Receiver<NodeEvent> {
+        self.add_subscriber(true)
+    }
+
+    fn add_subscriber(&self, lossy: bool) -> Receiver<NodeEvent> {
+        let (tx, rx) = mpsc::channel(SUBSCRIBER_CAPACITY);
+        self.subscribers
+            .lock()
+            .unwrap()
+            .push(Subscriber { tx, lossy });
+        rx
+    }
+
+    pub async fn publish(&self, event: NodeEvent) {
+        let subscribers = self.subscribers.lock().unwrap().clone();
+        let mut closed = false;
+        for subscriber in subscribers {
+            let result = if subscriber.lossy && event.is_progress() {
+                match subscriber.tx.try_send(event.clone()) {
+                    Err(mpsc::error::TrySendError::Closed(_)) => Err(()),
+                    _ => Ok(()),
+                }
+            } else {
+                subscriber.tx.send(event.clone()).await.map_err(|_| ())
+            };
+            closed |= result.is_err();
+        }
+        if closed {
+            self.subscribers
+                .lock()
+                .unwrap()
+                .retain(|subscriber| !subscriber.tx.is_closed());
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct RootNotifier {
     idx: NodeIndex,
-    tx: Sender<NodeEvent>,
+    bus: EventBus,
+    report: Option<SharedReport>,
 }
 
 impl RootNotifier {
-    pub fn new(tx: Sender<NodeEvent>, idx: NodeIndex) -> Self {
-        Self { idx, tx }
+    pub fn new(bus: EventBus, idx: NodeIndex) -> Self {
+        Self {
+            idx,
+            bus,
+            report: None,
+        }
+    }
+
+    pub fn idx(&self) -> &NodeIndex {
+        &self.idx
     }
+
+    // every event sent through the returned notifier is also added to the report
+    pub fn with_report(mut self, report: SharedReport) -> Self {
+        self.report = Some(report);
+        self
+    }
+
     pub async fn notify(&self, event: impl Into<NodeEventKind>) {
-        self.tx
-            .send(NodeEvent::new(event.into(), self.idx.clone()))
-            .await
-            .expect("Receiver was closed")
+        let event = NodeEvent::new(event.into(), self.idx.clone());
+        // the report has no use for progress, which comes with every few chunks
+        if let Some(report) = self.report.as_ref().filter(|_| !event.is_progress()) {
+            report.lock().unwrap().add(&event);
+        }
+        self.bus.publish(event).await
     }
 }
@@ -0,0 +1,107 @@
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use tokio::fs;
+use uuid::Uuid;
+
+use crate::error::{Result, TErrorKind};
+use crate::template::nodes::root::RawRootNode;
+
+use self::value::Value;
+
+pub mod value;
+
+// Bump this and append a migration to MIGRATIONS whenever the saved format changes
+pub const CURRENT_VERSION: u64 = 2;
+
+// MIGRATIONS[n] migrates the root of a template from version n to version n + 1.
+// They work on the untyped file, so every step only has to know the format before
+// and after it. The result is deserialized into the current RawRootNode.
+const MIGRATIONS: [fn(&mut Value) -> Result<()>; CURRENT_VERSION as usize] = [v0_to_v1, v1_to_v2];
+
+#[derive(Serialize)]
+struct TemplateFileRef<'a> {
+    version: u64,
+    root: &'a RawRootNode,
+}
+
+pub fn serialize(root: &RawRootNode) -> Result<String> {
+    Ok(ron::ser::to_string(&TemplateFileRef {
+        version: CURRENT_VERSION,
+        root,
+    })?)
+}
+
+// Returns the root and the version the file was saved with
+pub fn deserialize(bytes: &[u8]) -> Result<(RawRootNode, u64)> {
+    let text = std::str::from_utf8(bytes)
+        .map_err(|_| TErrorKind::TemplateSyntax("the file is not utf-8".to_owned()))?;
+    let mut file = Value::parse(text)?;
+
+    // version 0 had no header, the file was a bare RawRootNode
+    let (version, mut root) = match file.remove_field("version") {
+        Some(version) => {
+            let version = match &version {
+                Value::Atom(atom) => atom.parse::<u64>().ok(),
+                _ => None,
+            }
+            .ok_or_else(|| TErrorKind::TemplateSyntax(format!("bad version {}", version)))?;
+            let root = file
+                .remove_field("root")
+                .ok_or_else(|| TErrorKind::TemplateSyntax("missing root".to_owned()))?;
+            (version, root)
+        }
+        None => (0, file),
+    };
+    if version > CURRENT_VERSION {
+        return Err(TErrorKind::TemplateVersion {
+            found: version,
+            supported: CURRENT_VERSION,
+        }
+        .into());
+    }
+
+    for migration in &MIGRATIONS[version as usize..] {
+        migration(&mut root)?;
+    }
+    Ok((ron::de::from_str(&root.to_string())?, version))
+}
+
+// Keeps a copy of a template from an older version, as the next save overwrites it
+pub async fn backup(path: &Path, version: u64) -> Result<PathBuf> {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(format!(".v{}.bak", version));
+    let backup_path = path.with_file_name(file_name);
+    fs::copy(path, &backup_path).await?;
+    Ok(backup_path)
+}
+
+// calls f for every node below the root, depth first
+fn for_each_node(node: &mut Value, f: &mut impl FnMut(&mut Value) -> Result<()>) -> Result<()> {
+    if let Some(Value::List(children)) = node.field_mut("children") {
+        for child in children {
+            f(child)?;
+            for_each_node(child, f)?;
+        }
+    }
+    Ok(())
+}
+
+fn v0_to_v1(_root: &mut Value) -> Result<()> {
+    // only the version header was added
+    Ok(())
+}
+
+// Sites got a stable id, which names their state file. The storage embedded in these
+// templates is still read and moved to the state files on the next save, see template::state.
+fn v1_to_v2(root: &mut Value) -> Result<()> {
+    for_each_node(root, &mut |node| {
+        let site = node.field_mut("ty").and_then(|ty| ty.variant_mut("Site"));
+        if let Some(site) = site {
+            if site.field("id").is_none() {
+                site.set_field("id", Value::string(&Uuid::new_v4().to_string())?);
+            }
+        }
+        Ok(())
+    })
+}
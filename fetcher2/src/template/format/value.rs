@@ -0,0 +1,314 @@
+use std::fmt;
+
+use crate::error::{Result, TError, TErrorKind};
+
+// A parsed ron document that keeps the names of structs and enum variants,
+// which ron::Value drops. The migrations of old templates work on this.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    // numbers, strings, chars, booleans and unit variants, exactly as written
+    Atom(String),
+    // Name(field: value, ...), the name is optional
+    Struct(Option<String>, Vec<(String, Value)>),
+    // Name(value, ...), also used for Some(..) and newtype variants
+    Tuple(Option<String>, Vec<Value>),
+    List(Vec<Value>),
+    Map(Vec<(Value, Value)>),
+}
+
+impl Value {
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut parser = Parser { text, pos: 0 };
+        let value = parser.value()?;
+        parser.skip_ws();
+        if parser.pos < text.len() {
+            return Err(parser.error("expected the end of the file"));
+        }
+        Ok(value)
+    }
+
+    pub fn string(s: &str) -> Result<Self> {
+        Ok(Self::Atom(ron::ser::to_string(s)?))
+    }
+
+    pub fn field(&self, name: &str) -> Option<&Value> {
+        match self {
+            Self::Struct(_, fields) => fields
+                .iter()
+                .find(|(field, _)| field == name)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn field_mut(&mut self, name: &str) -> Option<&mut Value> {
+        match self {
+            Self::Struct(_, fields) => fields
+                .iter_mut()
+                .find(|(field, _)| field == name)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    // replaces the field if it already exists, does nothing for anything but structs
+    pub fn set_field(&mut self, name: &str, value: Value) {
+        if let Self::Struct(_, fields) = self {
+            match fields.iter_mut().find(|(field, _)| field == name) {
+                Some((_, old)) => *old = value,
+                None => fields.push((name.to_owned(), value)),
+            }
+        }
+    }
+
+    pub fn remove_field(&mut self, name: &str) -> Option<Value> {
+        match self {
+            Self::Struct(_, fields) => {
+                let idx = fields.iter().position(|(field, _)| field == name)?;
+                Some(fields.remove(idx).1)
+            }
+            _ => None,
+        }
+    }
+
+    // the content of a newtype variant like Site(..), if the variant has this name
+    pub fn variant_mut(&mut self, variant: &str) -> Option<&mut Value> {
+        match self {
+            Self::Tuple(Some(name), items) if name == variant && items.len() == 1 => {
+                items.first_mut()
+            }
+            _ => None,
+        }
+    }
+}
+
+// Compact ron, as written by ron::ser::to_string
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Atom(atom) => f.write_str(atom),
+            Self::Struct(name, fields) => {
+                write!(f, "{}(", name.as_deref().unwrap_or_default())?;
+                for (i, (field, value)) in fields.iter().enumerate() {
+                    let separator = if i == 0 { "" } else { "," };
+                    write!(f, "{}{}:{}", separator, field, value)?;
+                }
+                f.write_str(")")
+            }
+            Self::Tuple(name, items) => {
+                write!(f, "{}(", name.as_deref().unwrap_or_default())?;
+                write_items(f, items)?;
+                f.write_str(")")
+            }
+            Self::List(items) => {
+                f.write_str("[")?;
+                write_items(f, items)?;
+                f.write_str("]")
+            }
+            Self::Map(entries) => {
+                f.write_str("{")?;
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    let separator = if i == 0 { "" } else { "," };
+                    write!(f, "{}{}:{}", separator, key, value)?;
+                }
+                f.write_str("}")
+            }
+        }
+    }
+}
+
+fn write_items(f: &mut fmt::Formatter<'_>, items: &[Value]) -> fmt::Result {
+    for (i, item) in items.iter().enumerate() {
+        let separator = if i == 0 { "" } else { "," };
+        write!(f, "{}{}", separator, item)?;
+    }
+    Ok(())
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.text[self.pos..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn error(&self, msg: &str) -> TError {
+        TErrorKind::TemplateSyntax(format!("{} at byte {}", msg, self.pos)).into()
+    }
+
+    // whitespace, comments and attributes like #![enable(..)]
+    fn skip_ws(&mut self) {
+        loop {
+            let rest = self.rest();
+            let trimmed = rest.trim_start();
+            self.pos += rest.len() - trimmed.len();
+            let skipped = if trimmed.starts_with("//") {
+                trimmed.find('\n').unwrap_or(trimmed.len())
+            } else if trimmed.starts_with("/*") {
+                trimmed.find("*/").map_or(trimmed.len(), |end| end + 2)
+            } else if trimmed.starts_with("#!") {
+                trimmed.find(']').map_or(trimmed.len(), |end| end + 1)
+            } else {
+                return;
+            };
+            self.pos += skipped;
+        }
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_ws();
+        if self.peek() == Some(c) {
+            self.pos += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<()> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected {:?}", c)))
+        }
+    }
+
+    fn value(&mut self) -> Result<Value> {
+        self.skip_ws();
+        match self.peek() {
+            Some('(') => self.parenthesized(None),
+            Some('[') => {
+                self.pos += 1;
+                Ok(Value::List(self.items(']', Self::value)?))
+            }
+            Some('{') => {
+                self.pos += 1;
+                let entries = self.items('}', |parser| {
+                    let key = parser.value()?;
+                    parser.expect(':')?;
+                    Ok((key, parser.value()?))
+                })?;
+                Ok(Value::Map(entries))
+            }
+            Some('"') | Some('\'') => self.quoted(),
+            Some('r') if self.is_raw_string() => self.raw_string(),
+            Some(_) => {
+                let word = self.word()?;
+                let is_ident = word.starts_with(|c: char| c.is_alphabetic() || c == '_');
+                self.skip_ws();
+                if is_ident && self.peek() == Some('(') {
+                    self.parenthesized(Some(word))
+                } else {
+                    Ok(Value::Atom(word))
+                }
+            }
+            None => Err(self.error("unexpected end of the file")),
+        }
+    }
+
+    // identifiers and numbers
+    fn word(&mut self) -> Result<String> {
+        let len = self
+            .rest()
+            .find(|c: char| !(c.is_alphanumeric() || "_.+-".contains(c)))
+            .unwrap_or(self.rest().len());
+        if len == 0 {
+            return Err(self.error("unexpected character"));
+        }
+        let word = self.rest()[..len].to_owned();
+        self.pos += len;
+        Ok(word)
+    }
+
+    fn quoted(&mut self) -> Result<Value> {
+        let start = self.pos;
+        let mut chars = self.rest().char_indices();
+        let (_, quote) = chars.next().unwrap();
+        let mut escaped = false;
+        for (idx, c) in chars {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == quote {
+                self.pos += idx + c.len_utf8();
+                return Ok(Value::Atom(self.text[start..self.pos].to_owned()));
+            }
+        }
+        Err(self.error("unterminated string"))
+    }
+
+    fn is_raw_string(&self) -> bool {
+        self.rest()[1..].trim_start_matches('#').starts_with('"')
+    }
+
+    // r"..." or r#"..."#
+    fn raw_string(&mut self) -> Result<Value> {
+        let start = self.pos;
+        let hashes = self.rest()[1..].len() - self.rest()[1..].trim_start_matches('#').len();
+        let closing = format!("\"{}", "#".repeat(hashes));
+        let content_start = 2 + hashes;
+        match self.rest()[content_start..].find(&closing) {
+            Some(end) => {
+                self.pos += content_start + end + closing.len();
+                Ok(Value::Atom(self.text[start..self.pos].to_owned()))
+            }
+            None => Err(self.error("unterminated string")),
+        }
+    }
+
+    // a struct if the first entry is a field name followed by a colon, otherwise a tuple
+    fn parenthesized(&mut self, name: Option<String>) -> Result<Value> {
+        self.expect('(')?;
+        if self.is_struct() {
+            let fields = self.items(')', |parser| {
+                parser.skip_ws();
+                let field = parser.word()?;
+                parser.expect(':')?;
+                Ok((field, parser.value()?))
+            })?;
+            Ok(Value::Struct(name, fields))
+        } else {
+            Ok(Value::Tuple(name, self.items(')', Self::value)?))
+        }
+    }
+
+    fn is_struct(&mut self) -> bool {
+        self.skip_ws();
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        let after = rest[len..].trim_start();
+        len > 0
+            && rest.starts_with(|c: char| c.is_alphabetic() || c == '_')
+            && after.starts_with(':')
+            && !after.starts_with("::")
+    }
+
+    // comma separated until close, a trailing comma is allowed
+    fn items<T>(
+        &mut self,
+        close: char,
+        mut item: impl FnMut(&mut Self) -> Result<T>,
+    ) -> Result<Vec<T>> {
+        let mut items = Vec::new();
+        loop {
+            if self.eat(close) {
+                return Ok(items);
+            }
+            items.push(item(self)?);
+            if !self.eat(',') {
+                self.expect(close)?;
+                return Ok(items);
+            }
+        }
+    }
+}
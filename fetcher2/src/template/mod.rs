@@ -6,23 +6,35 @@ use std::pin::Pin;
 use std::sync::Arc;
 
 use tokio::fs;
-use tokio::io::AsyncWriteExt;
-use tokio::sync::mpsc;
 use tokio::sync::mpsc::Receiver;
+use tracing::{error, info_span, instrument, warn, Instrument};
+use uuid::Uuid;
 
-use crate::error::Result;
+use crate::error::{Result, TErrorKind};
+use crate::metrics::metrics;
 use crate::session::Session;
 use crate::settings::DownloadSettings;
+use crate::template::communication::EventBus;
 pub use crate::template::node_type::{DownloadArgs, Extensions, Mode};
 use crate::template::nodes::node::{NodeEvent, Status};
 use crate::template::nodes::root::{RawRootNode, RootNode};
+use crate::template::report::{RunReport, RunReportBuilder};
+use crate::template::tags::TagExpr;
+use crate::utils::write_atomic;
 
 pub mod communication;
+pub mod format;
 pub mod node_type;
 pub mod nodes;
+pub mod report;
+pub mod state;
+pub mod tags;
 
 pub type NodeIndex = im::Vector<usize>;
 
+// identifies all history entries of one run
+pub type RunId = Uuid;
+
 #[derive(Debug)]
 pub struct UnPrepared;
 #[derive(Debug)]
@@ -32,10 +44,25 @@ pub struct Prepared;
 pub struct Template<T> {
     pub root: RootNode,
     pub save_path: Option<PathBuf>,
+    pub(crate) bus: EventBus,
     _m: PhantomData<T>,
 }
 
 impl<T> Template<T> {
+    // all sites whose own or inherited tags match expr, usable as indexes for run
+    pub fn select(&self, expr: &TagExpr) -> HashSet<NodeIndex> {
+        self.root.select(expr)
+    }
+
+    pub fn subscribe(&self) -> Receiver<NodeEvent> {
+        self.bus.subscribe()
+    }
+
+    // progress events are dropped while the receiver is behind
+    pub fn subscribe_lossy(&self) -> Receiver<NodeEvent> {
+        self.bus.subscribe_lossy()
+    }
+
     pub async fn inform_of_cancel(&self) {
         self.root.inform_of_cancel().await
     }
@@ -43,16 +70,9 @@ impl<T> Template<T> {
     pub async fn save(&self) -> Result<()> {
         if let Some(save_path) = &self.save_path {
             let raw_root = self.root.clone().raw();
-            let template_str = ron::ser::to_string(&raw_root)?;
-            let mut f = fs::OpenOptions::new()
-                .write(true)
-                .truncate(true)
-                .create(true)
-                .open(save_path)
-                .await?;
-            f.write_all(template_str.as_bytes()).await?;
-
-            f.shutdown().await?;
+            let template_str = format::serialize(&raw_root)?;
+            write_atomic(save_path, template_str.as_bytes()).await?;
+            state::save(&raw_root, &state::state_dir(save_path)).await?;
         }
         Ok(())
     }
@@ -63,6 +83,7 @@ impl Template<UnPrepared> {
         Self {
             root: RootNode::new(),
             save_path: None,
+            bus: EventBus::new(),
             _m: PhantomData,
         }
     }
@@ -71,11 +92,12 @@ impl Template<UnPrepared> {
         raw: RawRootNode,
         save_path: PathBuf,
     ) -> (Template<UnPrepared>, Receiver<NodeEvent>) {
-        let (tx, rx) = mpsc::channel(1024);
-        let root = raw.transform(tx);
+        let bus = EventBus::new();
+        let rx = bus.subscribe();
         let template = Self {
-            root,
+            root: raw.transform(bus.clone()),
             save_path: Some(save_path),
+            bus,
             _m: PhantomData,
         };
         (template, rx)
@@ -83,38 +105,126 @@ impl Template<UnPrepared> {
 
     pub async fn load(path: &Path) -> Result<(Template<UnPrepared>, Receiver<NodeEvent>)> {
         let x = fs::read(path).await?;
-        let raw_root: RawRootNode = ron::de::from_bytes(&x)?;
+        let (mut raw_root, version) = format::deserialize(&x)?;
+        if version < format::CURRENT_VERSION {
+            format::backup(path, version).await?;
+        }
+        state::load(&mut raw_root, &state::state_dir(path)).await?;
         Ok(Self::new(raw_root, path.to_owned()))
     }
 
+    #[instrument(skip_all)]
     pub async fn prepare(
         mut self,
         dsettings: Arc<DownloadSettings>,
     ) -> std::result::Result<Template<Prepared>, Template<UnPrepared>> {
-        let session = Session::new();
-        let status = Pin::new(&mut self.root).prepare(&session, dsettings).await;
+        let session = match Session::new(&dsettings) {
+            Ok(session) => session,
+            Err(err) => {
+                error!(error = %err, "Could not create session");
+                return Err(self);
+            }
+        };
+        let status = Pin::new(&mut self.root)
+            .prepare(&session, dsettings, None)
+            .await;
         if let Status::Success = status {
-            Ok(Template::<Prepared> {
-                root: self.root,
-                save_path: self.save_path,
-                _m: PhantomData,
-            })
+            Ok(self.into_prepared())
+        } else {
+            Err(self)
+        }
+    }
+
+    // Only prepares the nodes in indexes, their ancestors and everything below them
+    #[instrument(name = "prepare", skip_all)]
+    pub async fn prepare_nodes(
+        &mut self,
+        dsettings: Arc<DownloadSettings>,
+        indexes: &HashSet<NodeIndex>,
+    ) -> Status {
+        let session = match Session::new(&dsettings) {
+            Ok(session) => session,
+            Err(err) => {
+                error!(error = %err, "Could not create session");
+                return Status::Failure;
+            }
+        };
+        self.root.prepare(&session, dsettings, Some(indexes)).await
+    }
+
+    pub fn is_prepared(&self, indexes: &HashSet<NodeIndex>) -> bool {
+        self.root.is_prepared(Some(indexes))
+    }
+
+    pub fn try_into_prepared(
+        self,
+    ) -> std::result::Result<Template<Prepared>, Template<UnPrepared>> {
+        if self.root.is_prepared(None) {
+            Ok(self.into_prepared())
         } else {
             Err(self)
         }
     }
+
+    // The nodes in indexes must be prepared with prepare_nodes first
+    pub async fn run(
+        &self,
+        dsettings: Arc<DownloadSettings>,
+        indexes: &HashSet<NodeIndex>,
+    ) -> Result<RunReport> {
+        if !self.is_prepared(indexes) {
+            return Err(TErrorKind::PrepareFailed.into());
+        }
+        let session = Session::new(&dsettings)?;
+        Ok(run_with_report(&self.root, &session, dsettings, Some(indexes)).await)
+    }
+
+    fn into_prepared(self) -> Template<Prepared> {
+        Template::<Prepared> {
+            root: self.root,
+            save_path: self.save_path,
+            bus: self.bus,
+            _m: PhantomData,
+        }
+    }
 }
 
 impl Template<Prepared> {
-    pub async fn run_root(&self, dsettings: Arc<DownloadSettings>) {
-        let session = Session::new();
-        self.root.run(&session, dsettings, None).await
+    pub async fn run_root(&self, dsettings: Arc<DownloadSettings>) -> Result<RunReport> {
+        let session = Session::new(&dsettings)?;
+        Ok(run_with_report(&self.root, &session, dsettings, None).await)
+    }
+
+    pub async fn run(
+        &self,
+        dsettings: Arc<DownloadSettings>,
+        indexes: &HashSet<NodeIndex>,
+    ) -> Result<RunReport> {
+        let session = Session::new(&dsettings)?;
+        Ok(run_with_report(&self.root, &session, dsettings, Some(indexes)).await)
     }
+}
 
-    pub async fn run(&self, dsettings: Arc<DownloadSettings>, indexes: &HashSet<NodeIndex>) {
-        let session = Session::new();
-        self.root.run(&session, dsettings, Some(indexes)).await
+pub(crate) async fn run_with_report(
+    root: &RootNode,
+    session: &Session,
+    dsettings: Arc<DownloadSettings>,
+    indexes: Option<&HashSet<NodeIndex>>,
+) -> RunReport {
+    let run_id = Uuid::new_v4();
+    let report = RunReportBuilder::new(run_id).shared();
+    root.run(session, Arc::clone(&dsettings), indexes, &report)
+        .instrument(info_span!("run", %run_id))
+        .await;
+    let report = RunReportBuilder::finish_shared(report);
+
+    metrics().record_run(&report);
+    if let Some(textfile) = &dsettings.metrics.textfile {
+        if let Err(err) = metrics().write_textfile(textfile.as_path()).await {
+            warn!(error = %err, "Could not write the metrics");
+        }
     }
+    report
 }
 
 impl Default for Template<UnPrepared> {
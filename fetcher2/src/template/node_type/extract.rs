@@ -0,0 +1,209 @@
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+use flate2::read::GzDecoder;
+use futures::Future;
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+
+use crate::error::{Result, TError, TErrorKind};
+use crate::template::communication::RootNotifier;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArchiveKind {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+impl ArchiveKind {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_string_lossy().to_lowercase();
+        if name.ends_with(".zip") {
+            Some(Self::Zip)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(Self::TarGz)
+        } else if name.ends_with(".tar") {
+            Some(Self::Tar)
+        } else {
+            None
+        }
+    }
+
+    fn suffix_len(&self, name: &str) -> usize {
+        match self {
+            Self::Zip => ".zip".len(),
+            Self::Tar => ".tar".len(),
+            Self::TarGz if name.to_lowercase().ends_with(".tgz") => ".tgz".len(),
+            Self::TarGz => ".tar.gz".len(),
+        }
+    }
+
+    // exercise03.zip is extracted into the sibling folder exercise03
+    pub fn target_dir(&self, archive: &Path) -> PathBuf {
+        let name = archive
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        let stem = &name[..name.len() - self.suffix_len(&name)];
+        if stem.is_empty() {
+            archive.with_file_name("extracted")
+        } else {
+            archive.with_file_name(stem)
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ExtractedArchive {
+    // checksum of the archive the files were extracted from
+    pub file_checksum: String,
+    pub files: Vec<PathBuf>,
+}
+
+// Returns the full path and checksum of every extracted file.
+// The target folder is only replaced if it was created by a previous extraction.
+pub async fn extract(
+    archive: PathBuf,
+    kind: ArchiveKind,
+    target: PathBuf,
+    owns_target: bool,
+) -> Result<Vec<(PathBuf, String)>> {
+    tokio::task::spawn_blocking(move || {
+        if target.exists() && !owns_target {
+            return Err(TErrorKind::ExtractTargetExists(target).into());
+        }
+
+        let mut temp_name = target.file_name().unwrap_or_default().to_os_string();
+        temp_name.push(".extracting");
+        let temp_dir = target.with_file_name(temp_name);
+        if temp_dir.exists() {
+            std::fs::remove_dir_all(&temp_dir)?;
+        }
+        std::fs::create_dir_all(&temp_dir)?;
+
+        let extracted = match kind {
+            ArchiveKind::Zip => extract_zip(&archive, &temp_dir),
+            ArchiveKind::Tar => extract_tar(File::open(&archive)?, &temp_dir),
+            ArchiveKind::TarGz => extract_tar(GzDecoder::new(File::open(&archive)?), &temp_dir),
+        };
+        let extracted = match extracted {
+            Ok(extracted) => extracted,
+            Err(err) => {
+                std::fs::remove_dir_all(&temp_dir)?;
+                return Err(err);
+            }
+        };
+
+        if target.exists() {
+            std::fs::remove_dir_all(&target)?;
+        }
+        std::fs::rename(&temp_dir, &target)?;
+
+        Ok(extracted
+            .into_iter()
+            .map(|(rel_path, checksum)| (target.join(rel_path), checksum))
+            .collect())
+    })
+    .await?
+}
+
+fn extract_zip(archive: &Path, dir: &Path) -> Result<Vec<(PathBuf, String)>> {
+    let mut zip = zip::ZipArchive::new(File::open(archive)?)?;
+    let mut extracted = Vec::new();
+    for i in 0..zip.len() {
+        let mut entry = zip.by_index(i)?;
+        let rel_path = enclosed_path(Path::new(entry.name()))
+            .ok_or_else(|| TErrorKind::UnsafeArchivePath(entry.name().to_owned()))?;
+        if entry.is_dir() {
+            std::fs::create_dir_all(dir.join(&rel_path))?;
+        } else {
+            let checksum = write_entry(&mut entry, &dir.join(&rel_path))?;
+            extracted.push((rel_path, checksum));
+        }
+    }
+    Ok(extracted)
+}
+
+fn extract_tar(reader: impl Read, dir: &Path) -> Result<Vec<(PathBuf, String)>> {
+    let mut tar = tar::Archive::new(reader);
+    let mut extracted = Vec::new();
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        let rel_path = enclosed_path(&path)
+            .ok_or_else(|| TErrorKind::UnsafeArchivePath(path.to_string_lossy().to_string()))?;
+        match entry.header().entry_type() {
+            tar::EntryType::Directory => std::fs::create_dir_all(dir.join(&rel_path))?,
+            tar::EntryType::Regular => {
+                let checksum = write_entry(&mut entry, &dir.join(&rel_path))?;
+                extracted.push((rel_path, checksum));
+            }
+            // links could point outside of the folder
+            _ => continue,
+        }
+    }
+    Ok(extracted)
+}
+
+// Only allows paths that stay inside the target folder (zip-slip)
+pub fn enclosed_path(path: &Path) -> Option<PathBuf> {
+    let mut enclosed = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => enclosed.push(part),
+            Component::CurDir => (),
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    if enclosed.as_os_str().is_empty() {
+        None
+    } else {
+        Some(enclosed)
+    }
+}
+
+fn write_entry(reader: &mut impl Read, path: &Path) -> Result<String> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut f = File::create(path)?;
+    let mut hasher = Sha1::new();
+    let mut buffer = [0u8; 64 * 1024];
+    loop {
+        let chunk_size = reader.read(&mut buffer)?;
+        if chunk_size == 0 {
+            break;
+        }
+        hasher.update(&buffer[..chunk_size]);
+        f.write_all(&buffer[..chunk_size])?;
+    }
+    Ok(String::from_utf8_lossy(&hasher.finalize()[..]).into_owned())
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ExtractMsg {
+    pub archive: PathBuf,
+    pub rel_archive: PathBuf,
+    pub files: usize,
+}
+
+#[derive(Debug, Clone)]
+pub enum ExtractEventKind {
+    Start,
+    Finish(ExtractMsg),
+    Err(Arc<TError>),
+}
+
+impl ExtractEventKind {
+    pub async fn wrapper(inner_fn: impl Future<Output = Result<ExtractMsg>>, tx: &RootNotifier) {
+        tx.notify(Self::Start).await;
+        match inner_fn.await {
+            Ok(msg) => tx.notify(Self::Finish(msg)).await,
+            Err(err) => tx.notify(Self::Err(Arc::new(err))).await,
+        }
+    }
+}
@@ -6,10 +6,14 @@ use serde::Serialize;
 use config::traveller::Travel;
 
 use crate::error::Result;
+use crate::settings::SettingsOverrides;
 
 #[derive(Travel, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Folder {
     pub name: String,
+
+    #[serde(default, skip_serializing_if = "SettingsOverrides::is_empty")]
+    pub overrides: SettingsOverrides,
 }
 
 impl Folder {
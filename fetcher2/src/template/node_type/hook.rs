@@ -0,0 +1,112 @@
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::Future;
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+
+use config::traveller::Travel;
+
+use crate::error::{Result, TError, TErrorKind};
+use crate::template::communication::RootNotifier;
+use crate::template::node_type::site::{MsgKind, TaskMsg};
+
+#[cfg_attr(feature = "druid", derive(druid::Data))]
+#[derive(Travel, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Hook {
+    /// Runs with sh (cmd on Windows) after a file was added or replaced.
+    /// The environment contains FETCHER2_FULL_PATH, FETCHER2_REL_PATH and
+    /// FETCHER2_EVENT (added or replaced)
+    #[travel(name = "Command")]
+    pub command: String,
+
+    #[travel(default = 60, name = "Timeout in Seconds")]
+    pub timeout: u64,
+}
+
+impl Hook {
+    pub fn should_run(msg: &TaskMsg) -> bool {
+        matches!(msg.kind, MsgKind::AddedFile | MsgKind::ReplacedFile(_))
+    }
+
+    pub async fn run(&self, msg: &TaskMsg) -> Result<HookOutput> {
+        let event = match &msg.kind {
+            MsgKind::ReplacedFile(_) => "replaced",
+            _ => "added",
+        };
+
+        let mut command = shell_command(&self.command);
+        command
+            .env("FETCHER2_FULL_PATH", &msg.full_path)
+            .env("FETCHER2_REL_PATH", &msg.rel_path)
+            .env("FETCHER2_EVENT", event)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+
+        let child = command.spawn()?;
+        let output =
+            tokio::time::timeout(Duration::from_secs(self.timeout), child.wait_with_output())
+                .await??;
+
+        let output = HookOutput {
+            full_path: msg.full_path.clone(),
+            exit_code: output.status.code(),
+            stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        };
+        if output.is_success() {
+            Ok(output)
+        } else {
+            Err(TErrorKind::HookFailed(output).into())
+        }
+    }
+}
+
+#[cfg(windows)]
+fn shell_command(command: &str) -> Command {
+    let mut cmd = Command::new("cmd");
+    cmd.arg("/C").arg(command);
+    cmd
+}
+
+#[cfg(not(windows))]
+fn shell_command(command: &str) -> Command {
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c").arg(command);
+    cmd
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HookOutput {
+    pub full_path: PathBuf,
+    pub exit_code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl HookOutput {
+    pub fn is_success(&self) -> bool {
+        self.exit_code == Some(0)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum HookEventKind {
+    Start,
+    Finish(HookOutput),
+    Err(Arc<TError>),
+}
+
+impl HookEventKind {
+    pub async fn wrapper(inner_fn: impl Future<Output = Result<HookOutput>>, tx: &RootNotifier) {
+        tx.notify(Self::Start).await;
+        match inner_fn.await {
+            Ok(output) => tx.notify(Self::Finish(output)).await,
+            Err(err) => tx.notify(Self::Err(Arc::new(err))).await,
+        }
+    }
+}
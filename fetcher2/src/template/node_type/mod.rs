@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use serde::Deserialize;
@@ -6,14 +6,16 @@ use serde::Serialize;
 
 use crate::error::Result;
 use crate::session::Session;
-use crate::settings::DownloadSettings;
+use crate::settings::{DownloadSettings, SettingsOverrides};
 pub use crate::template::node_type::folder::Folder;
 pub use crate::template::node_type::site::Mode;
 pub use crate::template::node_type::site::Site;
 pub use crate::template::node_type::site::SiteStorage;
 pub use crate::template::node_type::site::{DownloadArgs, Extensions};
 
+pub mod extract;
 pub mod folder;
+pub mod hook;
 pub mod site;
 mod utils;
 
@@ -34,4 +36,19 @@ impl NodeType {
             NodeType::Site(site) => site.path_segment(session, dsettings).await,
         }
     }
+
+    // a folder knows its segment without asking the server, a renamed one must be moved
+    pub fn is_cached_segment_current(&self, segment: &Path) -> bool {
+        match self {
+            NodeType::Folder(folder) => segment == Path::new(&folder.name),
+            NodeType::Site(_) => true,
+        }
+    }
+
+    pub fn overrides(&self) -> Option<&SettingsOverrides> {
+        match self {
+            NodeType::Folder(folder) => Some(&folder.overrides),
+            NodeType::Site(_) => None,
+        }
+    }
 }
@@ -1,15 +1,17 @@
+use std::collections::HashMap;
 use std::path::Path;
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::sync::Mutex;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
+use chrono::{DateTime, Utc};
 use dashmap::mapref::entry::Entry;
 use dashmap::DashMap;
 use futures::prelude::*;
 use futures::stream::FuturesUnordered;
 use futures::stream::StreamExt;
-use reqwest::header::{HeaderMap, HeaderValue};
+use reqwest::header::{HeaderMap, HeaderValue, USER_AGENT};
 use reqwest::Request;
 use serde::{Deserialize, Serialize};
 use sha1::{Digest, Sha1};
@@ -18,27 +20,50 @@ use tokio::io::AsyncWriteExt;
 use tokio::join;
 use tokio::sync::mpsc::Receiver;
 use tokio::task::JoinError;
+use tracing::{debug, instrument, warn, Instrument};
 use url::Url;
+use uuid::Uuid;
 
 use config::traveller::Travel;
 
-use crate::error::{Result, TError, TErrorKind};
-use crate::session::Session;
-use crate::settings::DownloadSettings;
+use crate::error::{ErrorContext, Result, TError, TErrorFast, TErrorKind};
+use crate::session::{header_map, Session};
+use crate::settings::{DownloadSettings, HistoryRetention};
 use crate::site_modules::Module;
 use crate::task::Task;
 use crate::template::communication::RootNotifier;
+use crate::template::node_type::extract::{
+    extract, ArchiveKind, ExtractEventKind, ExtractMsg, ExtractedArchive,
+};
+use crate::template::node_type::hook::{Hook, HookEventKind};
 use crate::template::node_type::utils::{add_to_file_stem, extension_from_url};
 use crate::template::nodes::node::Status;
+use crate::template::RunId;
 use crate::utils::spawn_drop;
 
+// the progress of a download is sent at most this often
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Site {
+    #[serde(default = "Uuid::new_v4")]
+    pub id: Uuid,
+
     pub module: Module,
 
+    // saved in its own state file, see template::state
+    #[serde(default, skip_serializing)]
     pub storage: Arc<SiteStorage>,
 
     pub download_args: Option<DownloadArgs>,
+
+    // skips the certificate checks for this site only
+    #[serde(default)]
+    pub insecure: bool,
+
+    // added to the headers from the settings
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
 }
 
 impl Site {
@@ -47,26 +72,63 @@ impl Site {
         session: &Session,
         dsettings: &DownloadSettings,
     ) -> Result<PathBuf> {
-        self.module.login(session, dsettings).await?;
-        self.module.folder_name(session, dsettings).await
+        let session = self.session(session, dsettings)?;
+        self.module.login(&session, dsettings).await?;
+        self.module.folder_name(&session, dsettings).await
+    }
+
+    // insecure sites get their own session, so the shared one keeps checking certificates
+    fn session(&self, session: &Session, dsettings: &DownloadSettings) -> Result<Session> {
+        let session = session.for_credentials(dsettings)?;
+        let session = if self.insecure {
+            session.insecure(dsettings)?
+        } else {
+            session.clone()
+        }
+        .with_rate_limit(self.module.rate_limit());
+
+        let mut headers = HeaderMap::new();
+        if let Some(user_agent) = self.module.user_agent() {
+            if !header_map(&dsettings.headers)?.contains_key(USER_AGENT) {
+                headers.insert(USER_AGENT, HeaderValue::from_static(user_agent));
+            }
+        }
+        headers.extend(header_map(&self.headers)?);
+        if headers.is_empty() {
+            Ok(session)
+        } else {
+            Ok(session.with_headers(headers))
+        }
     }
 
+    #[instrument(name = "site", skip_all, fields(module = %self.module.name()))]
     pub async fn run(
         self: Arc<Self>,
         session: Session,
         dsettings: Arc<DownloadSettings>,
         base_path: PathBuf,
         tx: RootNotifier,
+        run_id: RunId,
     ) {
         RunEventKind::wrapper(
             async {
-                if LoginEventKind::wrapper(self.module.login(&session, &dsettings), &tx)
-                    .await
-                    .is_none()
-                {
-                    return;
+                if self.insecure {
+                    tx.notify(SiteEventKind::Warning(
+                        "Certificates are not checked for this site".to_owned(),
+                    ))
+                    .await;
                 }
-
+                let login = async {
+                    let session = self.session(&session, &dsettings)?;
+                    self.module.login(&session, &dsettings).await?;
+                    Ok::<_, TError>(session)
+                };
+                let session = match LoginEventKind::wrapper(login, &tx).await {
+                    Some(session) => session,
+                    None => return,
+                };
+
+                let rate_limited = session.clone();
                 let (sender, receiver) = tokio::sync::mpsc::channel(1024);
 
                 let task_stream = UrlFetchEventKind::wrapper(
@@ -79,11 +141,17 @@ impl Site {
                     session,
                     receiver,
                     Arc::new(base_path),
-                    dsettings,
+                    Arc::clone(&dsettings),
                     tx.clone(),
+                    run_id,
                 );
 
                 join!(task_stream, consumers);
+                self.storage.prune_history(&dsettings.history);
+                let waited = rate_limited.waited();
+                if !waited.is_zero() {
+                    tx.notify(SiteEventKind::RateLimitWait(waited)).await;
+                }
             },
             &tx,
         )
@@ -97,6 +165,7 @@ impl Site {
         base_path: Arc<PathBuf>,
         dsettings: Arc<DownloadSettings>,
         tx: RootNotifier,
+        run_id: RunId,
     ) {
         let mut futs = FuturesUnordered::new();
         loop {
@@ -107,19 +176,19 @@ impl Site {
                     let handel: std::result::Result<_, JoinError> = handle;
                     handel.unwrap();
                 },
-                Some(task) = receiver.recv(), if futs.len() < 512 => {
+                Some(task) = receiver.recv(), if futs.len() < dsettings.max_concurrent() => {
                     let self_clone = Arc::clone(&self);
                     let handle = spawn_drop(
-                        DownloadEventKind::wrapper(
-                            self_clone.consume_task(
+                        self_clone
+                            .process_task(
                                 session.clone(),
                                 task,
                                 Arc::clone(&base_path),
                                 Arc::clone(&dsettings),
-                            ),
-                            tx.clone(),
-                            Arc::clone(&self),
-                        )
+                                tx.clone(),
+                                run_id,
+                            )
+                            .in_current_span()
                     );
                     futs.push(handle);
                 },
@@ -128,6 +197,126 @@ impl Site {
         }
     }
 
+    fn download_args<'a>(&'a self, dsettings: &'a DownloadSettings) -> &'a DownloadArgs {
+        self.download_args
+            .as_ref()
+            .unwrap_or(&dsettings.download_args)
+    }
+
+    #[instrument(
+        name = "task",
+        level = "debug",
+        skip_all,
+        fields(path = ?task.path, url = %task.url)
+    )]
+    async fn process_task(
+        self: Arc<Self>,
+        session: Session,
+        task: Task,
+        base_path: Arc<PathBuf>,
+        dsettings: Arc<DownloadSettings>,
+        tx: RootNotifier,
+        run_id: RunId,
+    ) -> Status {
+        let task_path = task.path.clone();
+        let node = ErrorContext::node(tx.idx());
+        let msg = match DownloadEventKind::wrapper(
+            Arc::clone(&self)
+                .consume_task(session, task, base_path, Arc::clone(&dsettings), tx.clone())
+                .map_err(|err| {
+                    err.context(ErrorContext::Task(task_path))
+                        .context(ErrorContext::Operation("downloading"))
+                        .context(node)
+                }),
+            &tx,
+            Arc::clone(&self),
+            run_id,
+        )
+        .await
+        {
+            Some(msg) => msg,
+            None => return Status::Failure,
+        };
+
+        if self.download_args(&dsettings).extract_archives {
+            if let Some(kind) = self.needs_extraction(&msg) {
+                ExtractEventKind::wrapper(self.extract_archive(&msg, kind), &tx).await;
+            }
+        }
+
+        if let Some(hook) = &self.download_args(&dsettings).hook {
+            if Hook::should_run(&msg) {
+                HookEventKind::wrapper(hook.run(&msg), &tx).await;
+            }
+        }
+        Status::Success
+    }
+
+    // extracts again if the archive changed since the last extraction
+    fn needs_extraction(&self, msg: &TaskMsg) -> Option<ArchiveKind> {
+        if let MsgKind::ForbiddenExtension(_) = msg.kind {
+            return None;
+        }
+        let kind = ArchiveKind::from_path(&msg.full_path)?;
+        let checksum = self
+            .storage
+            .files
+            .get(&msg.full_path)
+            .map(|file_data| file_data.file_checksum.clone())?;
+        match self.storage.extracted.get(&msg.full_path) {
+            Some(extracted) if extracted.file_checksum == checksum => None,
+            _ => Some(kind),
+        }
+    }
+
+    async fn extract_archive(&self, msg: &TaskMsg, kind: ArchiveKind) -> Result<ExtractMsg> {
+        let archive = msg.full_path.clone();
+        let checksum = self
+            .storage
+            .files
+            .get(&archive)
+            .map(|file_data| file_data.file_checksum.clone())
+            .wrong_format("archive checksum")?;
+        let previous = self
+            .storage
+            .extracted
+            .get(&archive)
+            .map(|extracted| extracted.files.clone());
+
+        let files = extract(
+            archive.clone(),
+            kind,
+            kind.target_dir(&archive),
+            previous.is_some(),
+        )
+        .await?;
+
+        for old_file in previous.unwrap_or_default() {
+            self.storage.files.remove(&old_file);
+        }
+        let mut extracted_files = Vec::with_capacity(files.len());
+        for (path, file_checksum) in files {
+            self.storage
+                .files
+                .insert(path.clone(), FileData::new(file_checksum, None, None));
+            extracted_files.push(path);
+        }
+        let count = extracted_files.len();
+        self.storage.extracted.insert(
+            archive.clone(),
+            ExtractedArchive {
+                file_checksum: checksum,
+                files: extracted_files,
+            },
+        );
+
+        Ok(ExtractMsg {
+            archive,
+            rel_archive: msg.rel_path.clone(),
+            files: count,
+        })
+    }
+
     // TODO: make sure it's fine to call this function twice with same arguments
     async fn consume_task(
         self: Arc<Self>,
@@ -135,11 +324,9 @@ impl Site {
         task: Task,
         base_path: Arc<PathBuf>,
         dsettings: Arc<DownloadSettings>,
+        tx: RootNotifier,
     ) -> Result<TaskMsg> {
-        let download_args = self
-            .download_args
-            .as_ref()
-            .unwrap_or(&dsettings.download_args);
+        let download_args = self.download_args(&dsettings);
 
         let Task {
             path: mut task_path,
@@ -179,7 +366,7 @@ impl Site {
             .extension()
             .map(|os_str| os_str.to_string_lossy().to_string());
         if download_args.extensions.is_extension_forbidden(&extension) {
-            println!("{:?}", final_path);
+            debug!(path = ?final_path, "Skipped forbidden extension");
             return Ok(TaskMsg::new(
                 final_path,
                 task_path,
@@ -231,6 +418,9 @@ impl Site {
         tokio::fs::create_dir_all(final_path.parent().unwrap()).await?;
 
         let mut hasher = Sha1::new();
+        let mut bytes = 0;
+        let total = response.content_length();
+        let mut last_progress: Option<Instant> = None;
 
         {
             let mut f = tokio::fs::OpenOptions::new()
@@ -244,7 +434,17 @@ impl Site {
                 tokio::time::timeout(Duration::from_secs(10), response.chunk()).await??
             {
                 hasher.update(&chunk);
-                f.write_all(&chunk).await?
+                bytes += chunk.len() as u64;
+                f.write_all(&chunk).await?;
+                if last_progress.map_or(true, |last| last.elapsed() >= PROGRESS_INTERVAL) {
+                    last_progress = Some(Instant::now());
+                    tx.notify(DownloadEventKind::Progress(DownloadProgress {
+                        rel_path: task_path.clone(),
+                        bytes,
+                        total,
+                    }))
+                    .await;
+                }
             }
 
             f.shutdown().await?;
@@ -264,11 +464,10 @@ impl Site {
                 if file_data.file_checksum == file_checksum {
                     file_data.etag = etag;
                     file_data.task_checksum = task_checksum;
-                    return Ok(TaskMsg::new(
-                        final_path,
-                        task_path,
-                        MsgKind::FileChecksumSame,
-                    ));
+                    return Ok(
+                        TaskMsg::new(final_path, task_path, MsgKind::FileChecksumSame)
+                            .with_bytes(bytes),
+                    );
                 }
             } else {
                 let current_file_checksum =
@@ -283,7 +482,8 @@ impl Site {
                                 final_path,
                                 task_path,
                                 MsgKind::FileChecksumSame,
-                            ));
+                            )
+                            .with_bytes(bytes));
                         }
                     }
                     Entry::Vacant(entry) => {
@@ -294,7 +494,8 @@ impl Site {
                                 final_path,
                                 task_path,
                                 MsgKind::FileChecksumSame,
-                            ));
+                            )
+                            .with_bytes(bytes));
                         }
                     }
                 }
@@ -320,14 +521,11 @@ impl Site {
             }
         }
 
-        match action {
-            Action::AddNew => Ok(TaskMsg::new(final_path, task_path, MsgKind::AddedFile)),
-            Action::Replace => Ok(TaskMsg::new(
-                final_path,
-                task_path,
-                MsgKind::ReplacedFile(old_path),
-            )),
-        }
+        let msg = match action {
+            Action::AddNew => TaskMsg::new(final_path, task_path, MsgKind::AddedFile),
+            Action::Replace => TaskMsg::new(final_path, task_path, MsgKind::ReplacedFile(old_path)),
+        };
+        Ok(msg.with_bytes(bytes))
     }
 
     async fn compute_file_checksum(path: &Path) -> Result<String> {
@@ -402,6 +600,17 @@ pub struct TaskMsg {
     pub full_path: PathBuf,
     #[cfg_attr(feature = "druid", data(same_fn = "PartialEq::eq"))]
     pub rel_path: PathBuf,
+    #[serde(default)]
+    #[travel(skip)]
+    #[cfg_attr(feature = "druid", data(same_fn = "PartialEq::eq"))]
+    pub time: Option<DateTime<Utc>>,
+    #[serde(default)]
+    #[travel(skip)]
+    #[cfg_attr(feature = "druid", data(same_fn = "PartialEq::eq"))]
+    pub run_id: Option<RunId>,
+    #[serde(default)]
+    #[travel(skip)]
+    pub bytes: u64,
 }
 
 impl TaskMsg {
@@ -410,8 +619,16 @@ impl TaskMsg {
             full_path,
             rel_path,
             kind,
+            time: None,
+            run_id: None,
+            bytes: 0,
         }
     }
+
+    pub fn with_bytes(mut self, bytes: u64) -> Self {
+        self.bytes = bytes;
+        self
+    }
 }
 
 #[cfg_attr(feature = "druid", derive(druid::Data))]
@@ -433,6 +650,14 @@ pub struct DownloadArgs {
 
     #[travel(default = true, name = "Keep Old Files")]
     pub keep_old_files: bool,
+
+    #[serde(default)]
+    #[travel(default = false, name = "Extract Archives")]
+    pub extract_archives: bool,
+
+    #[serde(default)]
+    #[travel(name = "Post-Download Hook")]
+    pub hook: Option<Hook>,
 }
 
 #[cfg_attr(feature = "druid", derive(druid::Data, druid::Lens))]
@@ -469,6 +694,10 @@ pub struct SiteStorage {
     pub files: dashmap::DashMap<PathBuf, FileData>,
 
     pub history: Mutex<Vec<TaskMsg>>,
+
+    // archive path -> extracted files, see DownloadArgs::extract_archives
+    #[serde(default)]
+    pub extracted: DashMap<PathBuf, ExtractedArchive>,
 }
 
 impl SiteStorage {
@@ -476,6 +705,58 @@ impl SiteStorage {
         Self {
             files: DashMap::new(),
             history: Mutex::new(Vec::new()),
+            extracted: DashMap::new(),
+        }
+    }
+
+    // entries without a time are only removed by max_entries
+    pub fn prune_history(&self, retention: &HistoryRetention) {
+        let mut history = self.history.lock().unwrap();
+        if let Some(max_days) = retention.max_days {
+            let oldest = Utc::now() - chrono::Duration::days(max_days as i64);
+            history.retain(|msg| msg.time.map_or(true, |time| time >= oldest));
+        }
+        if let Some(max_entries) = retention.max_entries {
+            let max_entries = max_entries as usize;
+            if history.len() > max_entries {
+                let overflow = history.len() - max_entries;
+                history.drain(..overflow);
+            }
+        }
+    }
+
+    pub fn move_files(&self, from: &Path, to: &Path) {
+        let moved_keys: Vec<PathBuf> = self
+            .files
+            .iter()
+            .filter(|entry| entry.key().starts_with(from))
+            .map(|entry| entry.key().clone())
+            .collect();
+
+        for key in moved_keys {
+            if let Some((_, file_data)) = self.files.remove(&key) {
+                let new_key = to.join(key.strip_prefix(from).unwrap());
+                self.files.insert(new_key, file_data);
+            }
+        }
+
+        let moved_archives: Vec<PathBuf> = self
+            .extracted
+            .iter()
+            .filter(|entry| entry.key().starts_with(from))
+            .map(|entry| entry.key().clone())
+            .collect();
+
+        for key in moved_archives {
+            if let Some((_, mut extracted)) = self.extracted.remove(&key) {
+                for file in extracted.files.iter_mut() {
+                    if let Ok(rel_file) = file.strip_prefix(from) {
+                        *file = to.join(rel_file);
+                    }
+                }
+                let new_key = to.join(key.strip_prefix(from).unwrap());
+                self.extracted.insert(new_key, extracted);
+            }
         }
     }
 }
@@ -503,12 +784,17 @@ impl FileData {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum SiteEventKind {
     Run(RunEventKind),
     Login(LoginEventKind),
     UrlFetch(UrlFetchEventKind),
     Download(DownloadEventKind),
+    Hook(HookEventKind),
+    Extract(ExtractEventKind),
+    Warning(String),
+    // time the requests of a run were held back by the rate limits
+    RateLimitWait(Duration),
 }
 
 impl SiteEventKind {
@@ -541,7 +827,19 @@ impl From<DownloadEventKind> for SiteEventKind {
     }
 }
 
-#[derive(Debug)]
+impl From<ExtractEventKind> for SiteEventKind {
+    fn from(extract_status: ExtractEventKind) -> Self {
+        SiteEventKind::Extract(extract_status)
+    }
+}
+
+impl From<HookEventKind> for SiteEventKind {
+    fn from(hook_status: HookEventKind) -> Self {
+        SiteEventKind::Hook(hook_status)
+    }
+}
+
+#[derive(Debug, Clone)]
 pub enum RunEventKind {
     Start,
     Finish,
@@ -556,11 +854,11 @@ impl RunEventKind {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum LoginEventKind {
     Start,
     Finish,
-    Err(TError),
+    Err(Arc<TError>),
 }
 
 impl LoginEventKind {
@@ -575,18 +873,18 @@ impl LoginEventKind {
                 Some(r)
             }
             Err(err) => {
-                tx.notify(Self::Err(err)).await;
+                tx.notify(Self::Err(Arc::new(err))).await;
                 None
             }
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum UrlFetchEventKind {
     Start,
     Finish,
-    Err(TError),
+    Err(Arc<TError>),
 }
 
 impl UrlFetchEventKind {
@@ -601,37 +899,50 @@ impl UrlFetchEventKind {
                 Some(r)
             }
             Err(err) => {
-                tx.notify(Self::Err(err)).await;
+                tx.notify(Self::Err(Arc::new(err))).await;
                 None
             }
         }
     }
 }
 
-#[derive(Debug)]
+// sent for every received chunk, lossy subscribers may skip some of them
+#[derive(Serialize, Debug, Clone)]
+pub struct DownloadProgress {
+    pub rel_path: PathBuf,
+    pub bytes: u64,
+    pub total: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
 pub enum DownloadEventKind {
     Start,
+    Progress(DownloadProgress),
     Finish(TaskMsg),
-    Err(TError),
+    Err(Arc<TError>),
 }
 
 impl DownloadEventKind {
     pub async fn wrapper(
         inner_fn: impl Future<Output = Result<TaskMsg>>,
-        tx: RootNotifier,
+        tx: &RootNotifier,
         site: Arc<Site>,
-    ) -> Status {
+        run_id: RunId,
+    ) -> Option<TaskMsg> {
         tx.notify(Self::Start).await;
         match inner_fn.await {
-            Ok(msg) => {
+            Ok(mut msg) => {
+                msg.time = Some(Utc::now());
+                msg.run_id = Some(run_id);
                 site.storage.history.lock().unwrap().push(msg.clone());
-                dbg!("{:?}", &msg);
-                tx.notify(Self::Finish(msg)).await;
-                Status::Success
+                debug!(kind = ?msg.kind, "Finished download");
+                tx.notify(Self::Finish(msg.clone())).await;
+                Some(msg)
             }
             Err(err) => {
-                tx.notify(Self::Err(err)).await;
-                Status::Failure
+                warn!(error = %err, "Download failed");
+                tx.notify(Self::Err(Arc::new(err))).await;
+                None
             }
         }
     }
@@ -1,20 +1,26 @@
 use std::collections::HashSet;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use async_recursion::async_recursion;
+use chrono::{DateTime, Utc};
 use futures::future::join_all;
 use futures::prelude::*;
 use serde::Deserialize;
 use serde::Serialize;
-use tokio::sync::mpsc::Sender;
+use tokio::fs;
+use tracing::{instrument, Instrument};
 
-use crate::error::Result;
+use config::traveller::Travel;
+
+use crate::error::{ErrorContext, Result, TErrorKind};
 use crate::session::Session;
 use crate::settings::DownloadSettings;
-use crate::template::communication::RootNotifier;
-use crate::template::node_type::site::SiteEventKind;
+use crate::template::communication::{EventBus, RootNotifier};
+use crate::template::node_type::site::{DownloadEventKind, SiteEventKind};
 use crate::template::node_type::NodeType;
+use crate::template::report::SharedReport;
+use crate::template::tags::TagExpr;
 use crate::template::NodeIndex;
 use crate::utils::spawn_drop;
 use crate::TError;
@@ -25,16 +31,54 @@ pub enum Status {
     Failure,
 }
 
+#[cfg_attr(feature = "druid", derive(druid::Data))]
+#[derive(Travel, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum PathRefresh {
+    Never,
+    EachRun,
+    EveryDays(u64),
+}
+
+impl PathRefresh {
+    pub fn is_outdated(&self, cached_at: Option<&DateTime<Utc>>) -> bool {
+        match self {
+            Self::Never => false,
+            Self::EachRun => true,
+            Self::EveryDays(days) => cached_at.map_or(true, |cached_at| {
+                Utc::now() - *cached_at >= chrono::Duration::days(*days as i64)
+            }),
+        }
+    }
+}
+
+impl Default for PathRefresh {
+    fn default() -> Self {
+        Self::Never
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct RawNode {
     pub ty: NodeType,
     pub children: Vec<RawNode>,
 
     pub cached_path_segment: Option<PathBuf>,
+    #[serde(default)]
+    pub cached_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub path_refresh: PathRefresh,
+    #[serde(default = "enabled_default")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub tags: im::HashSet<String>,
+}
+
+fn enabled_default() -> bool {
+    true
 }
 
 impl RawNode {
-    pub fn transform(self, index: NodeIndex, tx: Sender<NodeEvent>) -> Node {
+    pub fn transform(self, index: NodeIndex, bus: EventBus) -> Node {
         Node {
             ty: self.ty,
             children: self
@@ -44,11 +88,15 @@ impl RawNode {
                 .map(|(idx, raw_node)| {
                     let mut new_index = index.clone();
                     new_index.push_back(idx);
-                    raw_node.transform(new_index, tx.clone())
+                    raw_node.transform(new_index, bus.clone())
                 })
                 .collect(),
             cached_path_segment: self.cached_path_segment,
-            tx: RootNotifier::new(tx, index.clone()),
+            cached_at: self.cached_at,
+            path_refresh: self.path_refresh,
+            enabled: self.enabled,
+            tags: self.tags,
+            tx: RootNotifier::new(bus, index.clone()),
             path: None,
             index,
         }
@@ -60,6 +108,10 @@ pub struct Node {
     pub ty: NodeType,
     pub children: Vec<Node>,
     pub cached_path_segment: Option<PathBuf>,
+    pub cached_at: Option<DateTime<Utc>>,
+    pub path_refresh: PathRefresh,
+    pub enabled: bool,
+    pub tags: im::HashSet<String>,
     pub tx: RootNotifier,
     pub index: NodeIndex,
     pub path: Option<PathBuf>,
@@ -71,52 +123,96 @@ impl Node {
             ty: self.ty,
             children: self.children.into_iter().map(|node| node.raw()).collect(),
             cached_path_segment: self.cached_path_segment,
+            cached_at: self.cached_at,
+            path_refresh: self.path_refresh,
+            enabled: self.enabled,
+            tags: self.tags,
+        }
+    }
+
+    pub fn is_prepared(&self) -> bool {
+        self.path.is_some()
+    }
+
+    // a node is selected if it is in indexes, an ancestor of one of them or below one of them
+    pub fn is_selected(&self, indexes: Option<&HashSet<NodeIndex>>) -> bool {
+        indexes.map_or(true, |indexes| {
+            indexes
+                .iter()
+                .any(|idx| is_prefix(idx, &self.index) || is_prefix(&self.index, idx))
+        })
+    }
+
+    // selecting a folder runs all sites below it
+    fn is_run(&self, indexes: Option<&HashSet<NodeIndex>>) -> bool {
+        indexes.map_or(true, |indexes| {
+            indexes.iter().any(|idx| is_prefix(idx, &self.index))
+        })
+    }
+
+    // tags are inherited from the ancestors. Only sites are selected, a selected folder
+    // would run all sites below it, including those its expression excludes
+    pub fn select(
+        &self,
+        expr: &TagExpr,
+        inherited: &im::HashSet<String>,
+        selected: &mut HashSet<NodeIndex>,
+    ) {
+        let tags = self.tags.clone().union(inherited.clone());
+        if matches!(self.ty, NodeType::Site(_)) && expr.matches(&tags) {
+            selected.insert(self.index.clone());
+        }
+        for child in &self.children {
+            child.select(expr, &tags, selected);
         }
     }
 
+    pub fn is_prepared_recursive(&self, indexes: Option<&HashSet<NodeIndex>>) -> bool {
+        if !self.enabled || !self.is_selected(indexes) {
+            return true;
+        }
+        self.is_prepared()
+            && self
+                .children
+                .iter()
+                .all(|child| child.is_prepared_recursive(indexes))
+    }
+
+    // indexes: None means all
+    #[instrument(name = "node", level = "debug", skip_all, fields(index = ?self.index))]
     #[async_recursion]
     pub async fn prepare<'a>(
         &'a mut self,
         session: &'a Session,
         dsettings: Arc<DownloadSettings>,
         base_path: PathBuf,
+        indexes: Option<&'a HashSet<NodeIndex>>,
     ) -> Status {
-        let path = if let Some(segment) = &self.cached_path_segment {
-            if segment.is_absolute() {
-                panic!("segment is not allowed to be absolute")
-            }
-            let path = base_path.join(segment);
-            self.tx.notify(PathEventKind::Cached(path.clone())).await;
-            path
-        } else {
-            match PathEventKind::wrapper(
-                async {
-                    self.ty
-                        .path_segment(session, &dsettings)
-                        .await
-                        .map(|segment| {
-                            if segment.is_absolute() {
-                                panic!("segment is not allowed to be absolute")
-                            }
-                            base_path.join(segment)
-                        })
-                },
-                &self.tx,
-            )
-            .await
-            {
+        if !self.is_selected(indexes) {
+            return Status::Success;
+        }
+
+        if !self.enabled {
+            self.tx.notify(NodeEventKind::Disabled).await;
+            return Status::Success;
+        }
+
+        let dsettings = self.resolve_settings(dsettings);
+        let path = match self.path.clone() {
+            Some(path) => path,
+            None => match self.prepare_path(session, &dsettings, base_path).await {
                 Some(path) => path,
                 None => return Status::Failure,
-            }
+            },
         };
 
-        self.path = Some(path.clone());
-
         let futures: Vec<_> = self
             .children
             .iter_mut()
             .enumerate()
-            .map(|(_idx, child)| child.prepare(session, Arc::clone(&dsettings), path.clone()))
+            .map(|(_idx, child)| {
+                child.prepare(session, Arc::clone(&dsettings), path.clone(), indexes)
+            })
             .collect();
 
         if join_all(futures)
@@ -129,32 +225,152 @@ impl Node {
             Status::Success
         }
     }
+
+    async fn prepare_path(
+        &mut self,
+        session: &Session,
+        dsettings: &DownloadSettings,
+        base_path: PathBuf,
+    ) -> Option<PathBuf> {
+        let cached_segment = self
+            .cached_path_segment
+            .clone()
+            .filter(|_| !self.path_refresh.is_outdated(self.cached_at.as_ref()))
+            .filter(|segment| self.ty.is_cached_segment_current(segment));
+
+        let path = if let Some(segment) = cached_segment {
+            if segment.is_absolute() {
+                panic!("segment is not allowed to be absolute")
+            }
+            let path = base_path.join(segment);
+            self.tx.notify(PathEventKind::Cached(path.clone())).await;
+            path
+        } else {
+            let tx = self.tx.clone();
+            let node = ErrorContext::node(tx.idx());
+            PathEventKind::wrapper(
+                self.refresh_path_segment(session, dsettings, &base_path)
+                    .map_err(|err| err.context(node)),
+                &tx,
+            )
+            .await?
+        };
+
+        self.path = Some(path.clone());
+        Some(path)
+    }
+
+    async fn refresh_path_segment(
+        &mut self,
+        session: &Session,
+        dsettings: &DownloadSettings,
+        base_path: &Path,
+    ) -> Result<PathBuf> {
+        let segment = self.ty.path_segment(session, dsettings).await?;
+        if segment.is_absolute() {
+            panic!("segment is not allowed to be absolute")
+        }
+
+        if let Some(old_segment) = &self.cached_path_segment {
+            if old_segment != &segment {
+                self.move_folder(
+                    dsettings,
+                    &base_path.join(old_segment),
+                    &base_path.join(&segment),
+                )
+                .await?;
+            }
+        }
+
+        self.cached_path_segment = Some(segment.clone());
+        self.cached_at = Some(Utc::now());
+        Ok(base_path.join(segment))
+    }
+
+    async fn move_folder(
+        &self,
+        dsettings: &DownloadSettings,
+        old_path: &Path,
+        new_path: &Path,
+    ) -> Result<()> {
+        let old_full_path = dsettings.save_path.join(old_path);
+        let new_full_path = dsettings.save_path.join(new_path);
+
+        if fs::metadata(&old_full_path).await.is_ok() {
+            if fs::metadata(&new_full_path).await.is_ok() {
+                return Err(TErrorKind::RenameTargetExists(new_full_path).into());
+            }
+            if let Some(parent) = new_full_path.parent() {
+                fs::create_dir_all(parent).await?;
+            }
+            fs::rename(&old_full_path, &new_full_path).await?;
+        }
+
+        self.move_storage(&old_full_path, &new_full_path);
+        Ok(())
+    }
+
+    // settings for this node and its descendants
+    fn resolve_settings(&self, dsettings: Arc<DownloadSettings>) -> Arc<DownloadSettings> {
+        match self.ty.overrides() {
+            Some(overrides) if !overrides.is_empty() => Arc::new(overrides.apply(&dsettings)),
+            _ => dsettings,
+        }
+    }
+
+    fn move_storage(&self, from: &Path, to: &Path) {
+        if let NodeType::Site(site) = &self.ty {
+            site.storage.move_files(from, to);
+        }
+        for child in &self.children {
+            child.move_storage(from, to);
+        }
+    }
+
+    #[instrument(name = "node", level = "debug", skip_all, fields(index = ?self.index))]
     #[async_recursion]
     pub async fn run<'a>(
         &'a self,
         session: &'a Session,
         dsettings: Arc<DownloadSettings>,
         indexes: Option<&'a HashSet<NodeIndex>>,
+        report: &'a SharedReport,
     ) {
+        if !self.enabled {
+            if self.is_selected(indexes) {
+                self.tx.notify(NodeEventKind::Disabled).await;
+            }
+            return;
+        }
+
+        let dsettings = self.resolve_settings(dsettings);
         let mut futures: Vec<_> = self
             .children
             .iter()
-            .map(|child| child.run(session, Arc::clone(&dsettings), indexes))
+            .map(|child| child.run(session, Arc::clone(&dsettings), indexes, report))
             .collect();
 
-        if indexes.map_or(true, |indexes| indexes.contains(&self.index)) {
+        if self.is_run(indexes) {
             if let NodeType::Site(site) = &self.ty {
+                let run_id = {
+                    let mut report = report.lock().unwrap();
+                    report.add_site(&self.index, site.module.name(), self.path.clone());
+                    report.run_id()
+                };
                 let site_clone = site.clone();
                 let handle = spawn_drop(
-                    site_clone.run(
-                        session.clone(),
-                        dsettings,
-                        self.path
-                            .as_ref()
-                            .expect("Called run before prepare")
-                            .clone(),
-                        self.tx.clone(),
-                    ),
+                    site_clone
+                        .run(
+                            session.clone(),
+                            dsettings,
+                            self.path
+                                .as_ref()
+                                .expect("Called run before prepare")
+                                .clone(),
+                            self.tx.clone().with_report(Arc::clone(report)),
+                            run_id,
+                        )
+                        .in_current_span(),
                 );
                 futures.push(Box::pin(async move { handle.await.unwrap() }))
             }
@@ -171,7 +387,7 @@ impl Node {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct NodeEvent {
     pub kind: NodeEventKind,
     pub idx: NodeIndex,
@@ -181,13 +397,21 @@ impl NodeEvent {
     pub fn new(kind: NodeEventKind, idx: NodeIndex) -> Self {
         Self { kind, idx }
     }
+
+    pub fn is_progress(&self) -> bool {
+        matches!(
+            self.kind,
+            NodeEventKind::Site(SiteEventKind::Download(DownloadEventKind::Progress(_)))
+        )
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum NodeEventKind {
     Path(PathEventKind),
     Site(SiteEventKind),
     Canceled,
+    Disabled,
 }
 
 impl From<PathEventKind> for NodeEventKind {
@@ -205,12 +429,12 @@ where
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum PathEventKind {
     Start,
     Cached(PathBuf),
     Finish(PathBuf),
-    Err(TError),
+    Err(Arc<TError>),
 }
 
 impl PathEventKind {
@@ -228,9 +452,13 @@ impl PathEventKind {
                 Some(data)
             }
             Err(err) => {
-                tx.notify(Self::Err(err)).await;
+                tx.notify(Self::Err(Arc::new(err))).await;
                 None
             }
         }
     }
 }
+
+fn is_prefix(prefix: &NodeIndex, idx: &NodeIndex) -> bool {
+    idx.len() >= prefix.len() && idx.take(prefix.len()) == *prefix
+}
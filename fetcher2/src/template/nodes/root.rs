@@ -6,11 +6,13 @@ use futures::future::join_all;
 use im::Vector;
 use serde::Deserialize;
 use serde::Serialize;
-use tokio::sync::mpsc::Sender;
 
 use crate::session::Session;
 use crate::settings::DownloadSettings;
-use crate::template::nodes::node::{Node, NodeEvent, RawNode, Status};
+use crate::template::communication::EventBus;
+use crate::template::nodes::node::{Node, RawNode, Status};
+use crate::template::report::SharedReport;
+use crate::template::tags::TagExpr;
 use crate::template::NodeIndex;
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -19,7 +21,7 @@ pub struct RawRootNode {
 }
 
 impl RawRootNode {
-    pub fn transform(self, tx: Sender<NodeEvent>) -> RootNode {
+    pub fn transform(self, bus: EventBus) -> RootNode {
         RootNode {
             children: self
                 .children
@@ -28,7 +30,7 @@ impl RawRootNode {
                 .map(|(idx, raw_node)| {
                     let mut node_idx = Vector::new();
                     node_idx.push_back(idx);
-                    raw_node.transform(node_idx, tx.clone())
+                    raw_node.transform(node_idx, bus.clone())
                 })
                 .collect(),
         }
@@ -52,12 +54,34 @@ impl RootNode {
         }
     }
 
-    pub async fn prepare(&mut self, session: &Session, dsettings: Arc<DownloadSettings>) -> Status {
+    pub fn select(&self, expr: &TagExpr) -> HashSet<NodeIndex> {
+        let mut selected = HashSet::new();
+        for child in &self.children {
+            child.select(expr, &im::HashSet::new(), &mut selected);
+        }
+        selected
+    }
+
+    pub fn is_prepared(&self, indexes: Option<&HashSet<NodeIndex>>) -> bool {
+        self.children
+            .iter()
+            .all(|child| child.is_prepared_recursive(indexes))
+    }
+
+    // indexes: None means all
+    pub async fn prepare(
+        &mut self,
+        session: &Session,
+        dsettings: Arc<DownloadSettings>,
+        indexes: Option<&HashSet<NodeIndex>>,
+    ) -> Status {
         let futures: Vec<_> = self
             .children
             .iter_mut()
             .enumerate()
-            .map(|(_idx, child)| child.prepare(session, Arc::clone(&dsettings), PathBuf::new()))
+            .map(|(_idx, child)| {
+                child.prepare(session, Arc::clone(&dsettings), PathBuf::new(), indexes)
+            })
             .collect();
 
         if join_all(futures)
@@ -77,11 +101,12 @@ impl RootNode {
         session: &Session,
         dsettings: Arc<DownloadSettings>,
         indexes: Option<&HashSet<NodeIndex>>,
+        report: &SharedReport,
     ) {
         let futures = self
             .children
             .iter()
-            .map(|child| child.run(session, Arc::clone(&dsettings), indexes));
+            .map(|child| child.run(session, Arc::clone(&dsettings), indexes, report));
 
         join_all(futures).await;
     }
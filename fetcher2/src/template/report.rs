@@ -0,0 +1,391 @@
+use std::fmt::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::error::Result;
+use crate::template::node_type::extract::ExtractEventKind;
+use crate::template::node_type::hook::{HookEventKind, HookOutput};
+use crate::template::node_type::site::{
+    DownloadEventKind, LoginEventKind, MsgKind, RunEventKind, SiteEventKind, UrlFetchEventKind,
+};
+use crate::template::nodes::node::{NodeEvent, NodeEventKind};
+use crate::template::{NodeIndex, RunId};
+
+pub type SharedReport = Arc<Mutex<RunReportBuilder>>;
+
+#[derive(Serialize, Debug, Clone)]
+pub struct RunReport {
+    pub run_id: RunId,
+    pub started: DateTime<Utc>,
+    pub finished: DateTime<Utc>,
+    pub sites: Vec<SiteReport>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SiteReport {
+    pub index: Vec<usize>,
+    pub module: String,
+    pub path: Option<PathBuf>,
+    pub added: Vec<PathBuf>,
+    pub replaced: Vec<PathBuf>,
+    pub unchanged: usize,
+    pub forbidden: usize,
+    pub errors: Vec<String>,
+    pub warnings: Vec<String>,
+    pub rate_limit_wait_secs: f64,
+    pub bytes: u64,
+    pub hooks: Vec<HookOutput>,
+    pub extracted: Vec<PathBuf>,
+    pub started: Option<DateTime<Utc>>,
+    pub finished: Option<DateTime<Utc>>,
+}
+
+impl SiteReport {
+    fn new(index: &NodeIndex, module: String, path: Option<PathBuf>) -> Self {
+        Self {
+            index: index.iter().copied().collect(),
+            module,
+            path,
+            added: Vec::new(),
+            replaced: Vec::new(),
+            unchanged: 0,
+            forbidden: 0,
+            errors: Vec::new(),
+            warnings: Vec::new(),
+            rate_limit_wait_secs: 0.,
+            bytes: 0,
+            hooks: Vec::new(),
+            extracted: Vec::new(),
+            started: None,
+            finished: None,
+        }
+    }
+
+    pub fn duration_secs(&self) -> Option<f64> {
+        match (self.started, self.finished) {
+            (Some(started), Some(finished)) => {
+                Some((finished - started).num_milliseconds() as f64 / 1000.)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn title(&self) -> String {
+        match &self.path {
+            Some(path) => format!("{} ({})", path.display(), self.module),
+            None => self.module.clone(),
+        }
+    }
+
+    fn update(&mut self, event: &SiteEventKind) {
+        match event {
+            SiteEventKind::Run(RunEventKind::Start) => self.started = Some(Utc::now()),
+            SiteEventKind::Run(RunEventKind::Finish) => self.finished = Some(Utc::now()),
+            SiteEventKind::Login(LoginEventKind::Err(err))
+            | SiteEventKind::UrlFetch(UrlFetchEventKind::Err(err))
+            | SiteEventKind::Download(DownloadEventKind::Err(err))
+            | SiteEventKind::Hook(HookEventKind::Err(err))
+            | SiteEventKind::Extract(ExtractEventKind::Err(err)) => {
+                self.errors.push(err.to_string())
+            }
+            SiteEventKind::Warning(warning) => self.warnings.push(warning.clone()),
+            SiteEventKind::RateLimitWait(waited) => {
+                self.rate_limit_wait_secs += waited.as_secs_f64()
+            }
+            SiteEventKind::Hook(HookEventKind::Finish(output)) => self.hooks.push(output.clone()),
+            SiteEventKind::Extract(ExtractEventKind::Finish(msg)) => {
+                self.extracted.push(msg.rel_archive.clone())
+            }
+            SiteEventKind::Download(DownloadEventKind::Finish(msg)) => {
+                self.bytes += msg.bytes;
+                match &msg.kind {
+                    MsgKind::AddedFile => self.added.push(msg.rel_path.clone()),
+                    MsgKind::ReplacedFile(_) => self.replaced.push(msg.rel_path.clone()),
+                    MsgKind::NotModified | MsgKind::FileChecksumSame | MsgKind::AlreadyExist => {
+                        self.unchanged += 1
+                    }
+                    MsgKind::ForbiddenExtension(_) => self.forbidden += 1,
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct RunReportBuilder {
+    run_id: RunId,
+    started: DateTime<Utc>,
+    sites: Vec<(NodeIndex, SiteReport)>,
+}
+
+impl RunReportBuilder {
+    pub fn new(run_id: RunId) -> Self {
+        Self {
+            run_id,
+            started: Utc::now(),
+            sites: Vec::new(),
+        }
+    }
+
+    pub fn shared(self) -> SharedReport {
+        Arc::new(Mutex::new(self))
+    }
+
+    pub fn run_id(&self) -> RunId {
+        self.run_id
+    }
+
+    pub fn add_site(&mut self, index: &NodeIndex, module: String, path: Option<PathBuf>) {
+        self.sites
+            .push((index.clone(), SiteReport::new(index, module, path)));
+    }
+
+    pub fn add(&mut self, event: &NodeEvent) {
+        if let NodeEventKind::Site(site_event) = &event.kind {
+            if let Some((_, site)) = self.sites.iter_mut().find(|(idx, _)| idx == &event.idx) {
+                site.update(site_event)
+            }
+        }
+    }
+
+    pub fn finish(self) -> RunReport {
+        let mut sites: Vec<_> = self.sites.into_iter().map(|(_, site)| site).collect();
+        sites.sort_by(|a, b| a.index.cmp(&b.index));
+        RunReport {
+            run_id: self.run_id,
+            started: self.started,
+            finished: Utc::now(),
+            sites,
+        }
+    }
+
+    pub fn finish_shared(report: SharedReport) -> RunReport {
+        match Arc::try_unwrap(report) {
+            Ok(report) => report.into_inner().unwrap().finish(),
+            Err(report) => {
+                let mut report = report.lock().unwrap();
+                let run_id = report.run_id;
+                std::mem::replace(&mut *report, RunReportBuilder::new(run_id)).finish()
+            }
+        }
+    }
+}
+
+impl RunReport {
+    pub fn added(&self) -> usize {
+        self.sites.iter().map(|site| site.added.len()).sum()
+    }
+
+    pub fn replaced(&self) -> usize {
+        self.sites.iter().map(|site| site.replaced.len()).sum()
+    }
+
+    pub fn errors(&self) -> usize {
+        self.sites.iter().map(|site| site.errors.len()).sum()
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.sites.iter().map(|site| site.bytes).sum()
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut out = String::new();
+        writeln!(
+            out,
+            "# Run Report {}",
+            self.started.format("%Y-%m-%d %H:%M")
+        )
+        .unwrap();
+        writeln!(out).unwrap();
+        writeln!(
+            out,
+            "{} new, {} replaced, {} errors, {} transferred in {}s",
+            self.added(),
+            self.replaced(),
+            self.errors(),
+            bytesize::ByteSize(self.bytes()),
+            (self.finished - self.started).num_seconds()
+        )
+        .unwrap();
+
+        for site in &self.sites {
+            writeln!(out).unwrap();
+            writeln!(out, "## {}", site.title()).unwrap();
+            writeln!(out).unwrap();
+            writeln!(
+                out,
+                "| Added | Replaced | Unchanged | Forbidden | Errors | Transferred | Duration |"
+            )
+            .unwrap();
+            writeln!(out, "|---|---|---|---|---|---|---|").unwrap();
+            writeln!(
+                out,
+                "| {} | {} | {} | {} | {} | {} | {} |",
+                site.added.len(),
+                site.replaced.len(),
+                site.unchanged,
+                site.forbidden,
+                site.errors.len(),
+                bytesize::ByteSize(site.bytes),
+                site.duration_secs()
+                    .map_or_else(|| "-".to_owned(), |secs| format!("{:.1}s", secs))
+            )
+            .unwrap();
+            if site.rate_limit_wait_secs > 0. {
+                writeln!(out).unwrap();
+                writeln!(
+                    out,
+                    "Waited {:.1}s for the rate limits",
+                    site.rate_limit_wait_secs
+                )
+                .unwrap();
+            }
+            for (title, paths) in [
+                ("New", &site.added),
+                ("Replaced", &site.replaced),
+                ("Extracted", &site.extracted),
+            ] {
+                if !paths.is_empty() {
+                    writeln!(out).unwrap();
+                    writeln!(out, "{}:", title).unwrap();
+                    for path in paths {
+                        writeln!(out, "- {}", path.display()).unwrap();
+                    }
+                }
+            }
+            if !site.hooks.is_empty() {
+                writeln!(out).unwrap();
+                writeln!(out, "Hooks:").unwrap();
+                for hook in &site.hooks {
+                    writeln!(out, "- {}", hook.full_path.display()).unwrap();
+                    for line in hook.stdout.lines() {
+                        writeln!(out, "    {}", line).unwrap();
+                    }
+                }
+            }
+            if !site.warnings.is_empty() {
+                writeln!(out).unwrap();
+                writeln!(out, "Warnings:").unwrap();
+                for warning in &site.warnings {
+                    writeln!(out, "- {}", warning).unwrap();
+                }
+            }
+            if !site.errors.is_empty() {
+                writeln!(out).unwrap();
+                writeln!(out, "Errors:").unwrap();
+                for err in &site.errors {
+                    writeln!(out, "- {}", err).unwrap();
+                }
+            }
+        }
+        out
+    }
+
+    pub fn to_html(&self) -> String {
+        let mut out = String::new();
+        let title = format!("Run Report {}", self.started.format("%Y-%m-%d %H:%M"));
+        writeln!(out, "<!DOCTYPE html>").unwrap();
+        writeln!(out, "<html><head><meta charset=\"utf-8\">").unwrap();
+        writeln!(out, "<title>{}</title>", title).unwrap();
+        writeln!(
+            out,
+            "<style>body{{font-family:sans-serif;margin:2em}}\
+            table{{border-collapse:collapse}}\
+            td,th{{border:1px solid #ccc;padding:4px 8px}}\
+            .err{{color:#b00}}</style>"
+        )
+        .unwrap();
+        writeln!(out, "</head><body>").unwrap();
+        writeln!(out, "<h1>{}</h1>", title).unwrap();
+        writeln!(
+            out,
+            "<p>{} new, {} replaced, {} errors, {} transferred in {}s</p>",
+            self.added(),
+            self.replaced(),
+            self.errors(),
+            bytesize::ByteSize(self.bytes()),
+            (self.finished - self.started).num_seconds()
+        )
+        .unwrap();
+
+        for site in &self.sites {
+            writeln!(out, "<h2>{}</h2>", escape(&site.title())).unwrap();
+            writeln!(out, "<table><tr><th>Added</th><th>Replaced</th><th>Unchanged</th><th>Forbidden</th><th>Errors</th><th>Transferred</th><th>Duration</th></tr>").unwrap();
+            writeln!(
+                out,
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr></table>",
+                site.added.len(),
+                site.replaced.len(),
+                site.unchanged,
+                site.forbidden,
+                site.errors.len(),
+                bytesize::ByteSize(site.bytes),
+                site.duration_secs()
+                    .map_or_else(|| "-".to_owned(), |secs| format!("{:.1}s", secs))
+            )
+            .unwrap();
+            if site.rate_limit_wait_secs > 0. {
+                writeln!(
+                    out,
+                    "<p>Waited {:.1}s for the rate limits</p>",
+                    site.rate_limit_wait_secs
+                )
+                .unwrap();
+            }
+            for (title, paths) in [
+                ("New", &site.added),
+                ("Replaced", &site.replaced),
+                ("Extracted", &site.extracted),
+            ] {
+                if !paths.is_empty() {
+                    writeln!(out, "<h3>{}</h3><ul>", title).unwrap();
+                    for path in paths {
+                        writeln!(out, "<li>{}</li>", escape(&path.to_string_lossy())).unwrap();
+                    }
+                    writeln!(out, "</ul>").unwrap();
+                }
+            }
+            if !site.hooks.is_empty() {
+                writeln!(out, "<h3>Hooks</h3><ul>").unwrap();
+                for hook in &site.hooks {
+                    writeln!(
+                        out,
+                        "<li>{}<pre>{}</pre></li>",
+                        escape(&hook.full_path.to_string_lossy()),
+                        escape(&hook.stdout)
+                    )
+                    .unwrap();
+                }
+                writeln!(out, "</ul>").unwrap();
+            }
+            if !site.warnings.is_empty() {
+                writeln!(out, "<h3>Warnings</h3><ul>").unwrap();
+                for warning in &site.warnings {
+                    writeln!(out, "<li>{}</li>", escape(warning)).unwrap();
+                }
+                writeln!(out, "</ul>").unwrap();
+            }
+            if !site.errors.is_empty() {
+                writeln!(out, "<h3>Errors</h3><ul class=\"err\">").unwrap();
+                for err in &site.errors {
+                    writeln!(out, "<li>{}</li>", escape(err)).unwrap();
+                }
+                writeln!(out, "</ul>").unwrap();
+            }
+        }
+        writeln!(out, "</body></html>").unwrap();
+        out
+    }
+}
+
+fn escape(text: &str) -> String {
+    html_escape::encode_text(text).into_owned()
+}
@@ -0,0 +1,90 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use tokio::fs;
+use uuid::Uuid;
+
+use crate::error::Result;
+use crate::template::node_type::{NodeType, Site, SiteStorage};
+use crate::template::nodes::node::RawNode;
+use crate::template::nodes::root::RawRootNode;
+use crate::utils::write_atomic;
+
+// The SiteStorage of every site lives in <template stem>.state/<site id>.ron
+// next to the template, so the template itself only holds the definitions.
+pub fn state_dir(template_path: &Path) -> PathBuf {
+    let mut dir_name = template_path.file_stem().unwrap_or_default().to_os_string();
+    dir_name.push(".state");
+    template_path.with_file_name(dir_name)
+}
+
+fn state_path(dir: &Path, site: &Site) -> PathBuf {
+    dir.join(format!("{}.ron", site.id))
+}
+
+// Sites without a state file keep their current storage,
+// which is how storage embedded in older templates gets migrated
+pub async fn load(root: &mut RawRootNode, dir: &Path) -> Result<()> {
+    let mut sites = Vec::new();
+    sites_mut(&mut root.children, &mut sites);
+    for site in sites {
+        let path = state_path(dir, site);
+        if fs::metadata(&path).await.is_err() {
+            continue;
+        }
+        let bytes = fs::read(&path).await?;
+        let storage: SiteStorage = ron::de::from_bytes(&bytes)?;
+        Arc::make_mut(site).storage = Arc::new(storage);
+    }
+    Ok(())
+}
+
+// The state files of sites that are no longer in the template are removed
+pub async fn save(root: &RawRootNode, dir: &Path) -> Result<()> {
+    let mut sites = Vec::new();
+    sites_ref(&root.children, &mut sites);
+    if sites.is_empty() && fs::metadata(dir).await.is_err() {
+        return Ok(());
+    }
+    fs::create_dir_all(dir).await?;
+    let mut saved = HashSet::new();
+    for site in sites {
+        let path = state_path(dir, site);
+        let storage_str = ron::ser::to_string(site.storage.as_ref())?;
+        write_atomic(&path, storage_str.as_bytes()).await?;
+        saved.insert(path);
+    }
+
+    let mut entries = fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        let is_state = path.extension().and_then(|ext| ext.to_str()) == Some("ron")
+            && path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .map_or(false, |stem| Uuid::parse_str(stem).is_ok());
+        if is_state && !saved.contains(&path) {
+            fs::remove_file(&path).await?;
+        }
+    }
+    Ok(())
+}
+
+fn sites_ref<'a>(nodes: &'a [RawNode], sites: &mut Vec<&'a Arc<Site>>) {
+    for node in nodes {
+        if let NodeType::Site(site) = &node.ty {
+            sites.push(site);
+        }
+        sites_ref(&node.children, sites);
+    }
+}
+
+fn sites_mut<'a>(nodes: &'a mut [RawNode], sites: &mut Vec<&'a mut Arc<Site>>) {
+    for node in nodes {
+        if let NodeType::Site(site) = &mut node.ty {
+            sites.push(site);
+        }
+        sites_mut(&mut node.children, sites);
+    }
+}
@@ -0,0 +1,135 @@
+use std::iter::Peekable;
+use std::str::{CharIndices, FromStr};
+
+use crate::error::{Result, TErrorKind};
+
+// Grammar (lowest to highest precedence):
+//   or   := and ('|' and)*
+//   and  := not ('&' not)*
+//   not  := '!' not | atom
+//   atom := tag | '(' or ')'
+#[derive(Debug, Clone, PartialEq)]
+pub enum TagExpr {
+    Tag(String),
+    Not(Box<TagExpr>),
+    And(Box<TagExpr>, Box<TagExpr>),
+    Or(Box<TagExpr>, Box<TagExpr>),
+}
+
+impl TagExpr {
+    pub fn matches(&self, tags: &im::HashSet<String>) -> bool {
+        match self {
+            Self::Tag(tag) => tags.contains(tag),
+            Self::Not(expr) => !expr.matches(tags),
+            Self::And(lhs, rhs) => lhs.matches(tags) && rhs.matches(tags),
+            Self::Or(lhs, rhs) => lhs.matches(tags) || rhs.matches(tags),
+        }
+    }
+}
+
+impl FromStr for TagExpr {
+    type Err = crate::TError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parser = Parser {
+            input: s,
+            chars: s.char_indices().peekable(),
+        };
+        let expr = parser.parse_or()?;
+        match parser.next_token()? {
+            None => Ok(expr),
+            Some(token) => Err(parser.error(format!("unexpected {:?}", token))),
+        }
+    }
+}
+
+fn is_tag_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '-' | '.')
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Tag(String),
+    Not,
+    And,
+    Or,
+    Open,
+    Close,
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, msg: String) -> crate::TError {
+        TErrorKind::TagExpression(format!("{} in {:?}", msg, self.input)).into()
+    }
+
+    fn peek_token(&mut self) -> Result<Option<Token>> {
+        let chars = self.chars.clone();
+        let token = self.next_token();
+        self.chars = chars;
+        token
+    }
+
+    fn next_token(&mut self) -> Result<Option<Token>> {
+        while self.chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+
+        let (start, c) = match self.chars.next() {
+            Some(next) => next,
+            None => return Ok(None),
+        };
+        let token = match c {
+            '!' => Token::Not,
+            '&' => Token::And,
+            '|' => Token::Or,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            c if is_tag_char(c) => {
+                let mut end = start + c.len_utf8();
+                while let Some((idx, c)) = self.chars.next_if(|(_, c)| is_tag_char(*c)) {
+                    end = idx + c.len_utf8();
+                }
+                Token::Tag(self.input[start..end].to_owned())
+            }
+            c => return Err(self.error(format!("unexpected character {:?}", c))),
+        };
+        Ok(Some(token))
+    }
+
+    fn parse_or(&mut self) -> Result<TagExpr> {
+        let mut expr = self.parse_and()?;
+        while self.peek_token()? == Some(Token::Or) {
+            self.next_token()?;
+            expr = TagExpr::Or(Box::new(expr), Box::new(self.parse_and()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> Result<TagExpr> {
+        let mut expr = self.parse_not()?;
+        while self.peek_token()? == Some(Token::And) {
+            self.next_token()?;
+            expr = TagExpr::And(Box::new(expr), Box::new(self.parse_not()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_not(&mut self) -> Result<TagExpr> {
+        match self.next_token()? {
+            Some(Token::Not) => Ok(TagExpr::Not(Box::new(self.parse_not()?))),
+            Some(Token::Tag(tag)) => Ok(TagExpr::Tag(tag)),
+            Some(Token::Open) => {
+                let expr = self.parse_or()?;
+                match self.next_token()? {
+                    Some(Token::Close) => Ok(expr),
+                    _ => Err(self.error("missing ')'".to_owned())),
+                }
+            }
+            Some(token) => Err(self.error(format!("unexpected {:?}", token))),
+            None => Err(self.error("unexpected end".to_owned())),
+        }
+    }
+}
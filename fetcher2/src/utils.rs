@@ -1,9 +1,15 @@
 use std::future::Future;
+use std::path::Path;
 use std::pin::Pin;
 use std::task::{Context, Poll};
 
 use futures::FutureExt;
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
 use tokio::task::{JoinError, JoinHandle};
+use uuid::Uuid;
+
+use crate::error::Result;
 
 pub struct JoinHandleDrop<T>(JoinHandle<T>);
 
@@ -28,3 +34,19 @@ where
 {
     JoinHandleDrop(tokio::spawn(future))
 }
+
+// Writes to a temp file first, so a crash never leaves a half written file behind.
+// The temp name is unique, writes of the same file may run at the same time.
+pub async fn write_atomic(path: &Path, contents: &[u8]) -> Result<()> {
+    let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(format!(".{}.tmp", Uuid::new_v4()));
+    let temp_path = path.with_file_name(temp_name);
+
+    let mut f = fs::File::create(&temp_path).await?;
+    f.write_all(contents).await?;
+    f.sync_all().await?;
+    drop(f);
+
+    fs::rename(&temp_path, path).await?;
+    Ok(())
+}
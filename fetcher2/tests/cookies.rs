@@ -0,0 +1,107 @@
+use std::sync::Arc;
+
+use cookie_store::CookieStore;
+use futures::future::join_all;
+use url::Url;
+
+use fetcher2::cookies::CookieJar;
+
+use support::temp_dir;
+
+mod support;
+
+fn store(cookies: &[(&str, &str)]) -> CookieStore {
+    let mut store = CookieStore::default();
+    for (url, cookie) in cookies {
+        store.parse(cookie, &Url::parse(url).unwrap()).unwrap();
+    }
+    store
+}
+
+#[tokio::test]
+async fn cookies_are_encrypted() {
+    let dir = temp_dir("cookies");
+    let jar = CookieJar::open_with_key_file(&dir).unwrap();
+    jar.save(&store(&[
+        (
+            "https://example.org/",
+            "session=secret-value; Domain=example.org",
+        ),
+        ("https://other.org/", "other=1"),
+    ]))
+    .await
+    .unwrap();
+
+    let file = std::fs::read(dir.join("example.org.cookies")).unwrap();
+    assert!(!String::from_utf8_lossy(&file).contains("secret-value"));
+
+    // a new jar finds the same key
+    let loaded = CookieJar::open_with_key_file(&dir).unwrap().load().unwrap();
+    let cookie = loaded.get("example.org", "/", "session").unwrap();
+    assert_eq!(cookie.value(), "secret-value");
+    assert_eq!(loaded.get("other.org", "/", "other").unwrap().value(), "1");
+
+    // with another key nothing can be read, that only costs a new login
+    std::fs::remove_file(dir.join("key")).unwrap();
+    let loaded = CookieJar::open_with_key_file(&dir).unwrap().load().unwrap();
+    assert_eq!(loaded.iter_any().count(), 0);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn domains_without_cookies_are_removed() {
+    let dir = temp_dir("cookies");
+    let jar = CookieJar::open_with_key_file(&dir).unwrap();
+    jar.save(&store(&[
+        ("https://example.org/", "session=1"),
+        ("https://other.org/", "other=1"),
+    ]))
+    .await
+    .unwrap();
+    assert!(dir.join("example.org.cookies").exists());
+
+    jar.save(&store(&[("https://other.org/", "other=2")]))
+        .await
+        .unwrap();
+    assert!(!dir.join("example.org.cookies").exists());
+    let loaded = jar.load().unwrap();
+    assert_eq!(loaded.get("other.org", "/", "other").unwrap().value(), "2");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn concurrent_saves_do_not_interfere() {
+    let dir = temp_dir("cookies");
+    let jar = Arc::new(CookieJar::open_with_key_file(&dir).unwrap());
+    let saves = (0..10).map(|i| {
+        let jar = Arc::clone(&jar);
+        tokio::spawn(async move {
+            let value = format!("session={}", i);
+            jar.save(&store(&[
+                ("https://example.org/", value.as_str()),
+                ("https://other.org/", value.as_str()),
+            ]))
+            .await
+        })
+    });
+    for result in join_all(saves).await {
+        result.unwrap().unwrap();
+    }
+
+    let loaded = jar.load().unwrap();
+    assert!(loaded.get("example.org", "/", "session").is_some());
+    assert!(loaded.get("other.org", "/", "session").is_some());
+    let mut files: Vec<_> = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    files.sort();
+    assert_eq!(
+        files,
+        vec!["example.org.cookies", "key", "other.org.cookies"]
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
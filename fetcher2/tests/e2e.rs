@@ -0,0 +1,475 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::StreamExt;
+use reqwest::StatusCode;
+use serde_json::json;
+use uuid::Uuid;
+
+use fetcher2::error::ErrorContext;
+use fetcher2::fetcher::{Fetcher, Selection};
+use fetcher2::session::Session;
+use fetcher2::settings::DownloadSettings;
+use fetcher2::site_modules::{Listing, Module};
+use fetcher2::template::node_type::hook::Hook;
+use fetcher2::template::node_type::site::{DownloadEventKind, MsgKind, SiteEventKind, TaskMsg};
+use fetcher2::template::node_type::{NodeType, Site, SiteStorage};
+use fetcher2::template::nodes::node::{NodeEvent, NodeEventKind, PathRefresh, RawNode};
+use fetcher2::template::nodes::root::RawRootNode;
+use fetcher2::template::report::RunReport;
+use fetcher2::template::{Prepared, Template};
+
+use support::{dsettings, temp_dir, Route, StandIn};
+
+mod support;
+
+struct Harness {
+    stand_in: StandIn,
+    dir: PathBuf,
+    dsettings: Arc<DownloadSettings>,
+    template: Template<Prepared>,
+}
+
+impl Harness {
+    async fn new(index: serde_json::Value) -> Self {
+        let stand_in = StandIn::start().await;
+        stand_in.serve("/index.json", Route::json(&index));
+        let dir = temp_dir("e2e");
+        let dsettings = Arc::new(dsettings(&dir));
+
+        let site = Site {
+            id: Uuid::new_v4(),
+            module: Module::Listing(Listing {
+                url: stand_in.url("/index.json"),
+                name: "Course".to_owned(),
+            }),
+            storage: Arc::new(SiteStorage::new()),
+            download_args: None,
+            insecure: false,
+            headers: HashMap::new(),
+        };
+        let raw = RawRootNode {
+            children: vec![RawNode {
+                ty: NodeType::Site(Arc::new(site)),
+                children: Vec::new(),
+                cached_path_segment: None,
+                cached_at: None,
+                path_refresh: PathRefresh::Never,
+                enabled: true,
+                tags: im::HashSet::new(),
+            }],
+        };
+        // every run subscribes on its own
+        let (template, _) = Template::new(raw, dir.join("template.ron"));
+        let template = template.prepare(Arc::clone(&dsettings)).await.unwrap();
+
+        Self {
+            stand_in,
+            dir,
+            dsettings,
+            template,
+        }
+    }
+
+    async fn run(&self) -> (RunReport, Vec<NodeEvent>) {
+        let mut rx = self.template.subscribe();
+        let report = self
+            .template
+            .run_root(Arc::clone(&self.dsettings))
+            .await
+            .unwrap();
+        let mut events = Vec::new();
+        while let Ok(event) = rx.try_recv() {
+            events.push(event);
+        }
+        (report, events)
+    }
+
+    fn path(&self, rel_path: &str) -> PathBuf {
+        self.dir.join("Course").join(rel_path)
+    }
+
+    fn read(&self, rel_path: &str) -> String {
+        std::fs::read_to_string(self.path(rel_path)).unwrap()
+    }
+
+    fn hook(&mut self, command: &str, timeout: u64) {
+        let mut dsettings = (*self.dsettings).clone();
+        dsettings.download_args.hook = Some(Hook {
+            command: command.to_owned(),
+            timeout,
+        });
+        self.dsettings = Arc::new(dsettings);
+    }
+
+    fn storage(&self) -> Arc<SiteStorage> {
+        match &self.template.root.children[0].ty {
+            NodeType::Site(site) => Arc::clone(&site.storage),
+            NodeType::Folder(_) => unreachable!(),
+        }
+    }
+}
+
+impl Drop for Harness {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn finished(events: &[NodeEvent]) -> HashMap<PathBuf, MsgKind> {
+    events
+        .iter()
+        .filter_map(|event| match &event.kind {
+            NodeEventKind::Site(SiteEventKind::Download(DownloadEventKind::Finish(TaskMsg {
+                rel_path,
+                kind,
+                ..
+            }))) => Some((rel_path.clone(), kind.clone())),
+            _ => None,
+        })
+        .collect()
+}
+
+fn kind<'a>(finished: &'a HashMap<PathBuf, MsgKind>, rel_path: &str) -> &'a MsgKind {
+    finished
+        .get(Path::new(rel_path))
+        .unwrap_or_else(|| panic!("{} was not finished", rel_path))
+}
+
+#[tokio::test]
+async fn downloads_and_updates_files() {
+    let harness = Harness::new(json!([
+        {"path": "slides/week1.pdf", "url": "/files/week1.pdf"},
+        {"path": "notes", "url": "/files/notes", "has_extension": false},
+        {"path": "setup.exe", "url": "/files/setup.exe"},
+        {"path": "big.bin", "url": "/files/big.bin"},
+        {"path": "many.bin", "url": "/files/many.bin"},
+    ]))
+    .await;
+    let stand_in = &harness.stand_in;
+    stand_in.serve("/files/week1.pdf", Route::file("week 1").etag("v1"));
+    stand_in.serve("/files/notes", Route::file("notes").attachment("notes.txt"));
+    stand_in.serve("/files/setup.exe", Route::file("binary"));
+    stand_in.serve(
+        "/files/big.bin",
+        Route::file(vec![7u8; 4000]).slow(4, Duration::from_millis(150)),
+    );
+    stand_in.serve(
+        "/files/many.bin",
+        Route::file(vec![7u8; 40000]).slow(100, Duration::from_millis(1)),
+    );
+
+    let (report, events) = harness.run().await;
+    let first = finished(&events);
+    assert_eq!(kind(&first, "slides/week1.pdf"), &MsgKind::AddedFile);
+    // the extension comes from the Content-Disposition header
+    assert_eq!(kind(&first, "notes.txt"), &MsgKind::AddedFile);
+    assert_eq!(kind(&first, "big.bin"), &MsgKind::AddedFile);
+    assert_eq!(
+        kind(&first, "setup.exe"),
+        &MsgKind::ForbiddenExtension(Some("exe".to_owned()))
+    );
+    assert!(stand_in.requests("/files/setup.exe").is_empty());
+    assert_eq!(report.added(), 4);
+    assert_eq!(report.errors(), 0);
+
+    assert_eq!(harness.read("slides/week1.pdf"), "week 1");
+    assert_eq!(harness.read("notes.txt"), "notes");
+    assert_eq!(std::fs::read(harness.path("big.bin")).unwrap().len(), 4000);
+    let progress = |rel_path: &str| {
+        events
+            .iter()
+            .filter(|event| match &event.kind {
+                NodeEventKind::Site(SiteEventKind::Download(DownloadEventKind::Progress(
+                    progress,
+                ))) => progress.rel_path == Path::new(rel_path),
+                _ => false,
+            })
+            .count()
+    };
+    assert_eq!(
+        progress("big.bin"),
+        4,
+        "slow downloads report their progress"
+    );
+    // but not for every chunk
+    assert!(progress("many.bin") < 10);
+
+    let storage = harness.storage();
+    let week1 = storage
+        .files
+        .get(&harness.path("slides/week1.pdf"))
+        .unwrap();
+    assert_eq!(week1.etag.as_deref(), Some("\"v1\""));
+    drop(week1);
+
+    // unchanged, the etag gets a 304 and files without one are not requested again
+    let (_, events) = harness.run().await;
+    let second = finished(&events);
+    assert_eq!(kind(&second, "slides/week1.pdf"), &MsgKind::NotModified);
+    assert_eq!(kind(&second, "big.bin"), &MsgKind::AlreadyExist);
+    assert_eq!(kind(&second, "notes.txt"), &MsgKind::AlreadyExist);
+    let requests = stand_in.requests("/files/week1.pdf");
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[1].method, "GET");
+    assert_eq!(requests[1].header("If-None-Match"), Some("\"v1\""));
+    assert_eq!(stand_in.requests("/files/big.bin").len(), 1);
+
+    // a new version replaces the file and keeps the old one next to it
+    stand_in.serve("/files/week1.pdf", Route::file("week 1, fixed").etag("v2"));
+    let (report, events) = harness.run().await;
+    let third = finished(&events);
+    assert_eq!(
+        kind(&third, "slides/week1.pdf"),
+        &MsgKind::ReplacedFile(harness.path("slides/week1-old.pdf"))
+    );
+    assert_eq!(report.replaced(), 1);
+    assert_eq!(harness.read("slides/week1.pdf"), "week 1, fixed");
+    assert_eq!(harness.read("slides/week1-old.pdf"), "week 1");
+    let week1 = storage
+        .files
+        .get(&harness.path("slides/week1.pdf"))
+        .unwrap();
+    assert_eq!(week1.etag.as_deref(), Some("\"v2\""));
+}
+
+#[tokio::test]
+async fn task_checksums_decide_about_downloads() {
+    let harness = Harness::new(json!([
+        {"path": "a.txt", "url": "/files/a.txt", "checksum": "1"},
+    ]))
+    .await;
+    let stand_in = &harness.stand_in;
+    stand_in.serve("/files/a.txt", Route::file("A"));
+
+    let (_, events) = harness.run().await;
+    assert_eq!(kind(&finished(&events), "a.txt"), &MsgKind::AddedFile);
+
+    // same checksum, nothing is requested
+    let (_, events) = harness.run().await;
+    assert_eq!(kind(&finished(&events), "a.txt"), &MsgKind::AlreadyExist);
+    assert_eq!(stand_in.requests("/files/a.txt").len(), 1);
+
+    // new checksum but the same content
+    stand_in.serve(
+        "/index.json",
+        Route::json(&json!([{"path": "a.txt", "url": "/files/a.txt", "checksum": "2"}])),
+    );
+    let (_, events) = harness.run().await;
+    assert_eq!(
+        kind(&finished(&events), "a.txt"),
+        &MsgKind::FileChecksumSame
+    );
+    assert!(!harness.path("a-old.txt").exists());
+    let storage = harness.storage();
+    let file_data = storage.files.get(&harness.path("a.txt")).unwrap();
+    assert_eq!(file_data.task_checksum.as_deref(), Some("2"));
+    drop(file_data);
+
+    // new checksum and new content
+    stand_in.serve(
+        "/index.json",
+        Route::json(&json!([{"path": "a.txt", "url": "/files/a.txt", "checksum": "3"}])),
+    );
+    stand_in.serve("/files/a.txt", Route::file("A2"));
+    let (_, events) = harness.run().await;
+    assert_eq!(
+        kind(&finished(&events), "a.txt"),
+        &MsgKind::ReplacedFile(harness.path("a-old.txt"))
+    );
+    assert_eq!(harness.read("a.txt"), "A2");
+    assert_eq!(harness.read("a-old.txt"), "A");
+}
+
+#[tokio::test]
+async fn failed_downloads_are_reported() {
+    let harness = Harness::new(json!([
+        {"path": "broken.pdf", "url": "/files/broken.pdf"},
+        {"path": "fine.pdf", "url": "/files/fine.pdf"},
+    ]))
+    .await;
+    // nothing is served for broken.pdf, a 404 is not worth a retry
+    harness
+        .stand_in
+        .serve("/files/fine.pdf", Route::file("fine"));
+
+    let (report, events) = harness.run().await;
+    let errors: Vec<_> = events
+        .iter()
+        .filter_map(|event| match &event.kind {
+            NodeEventKind::Site(SiteEventKind::Download(DownloadEventKind::Err(err))) => Some(err),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].status, Some(StatusCode::NOT_FOUND));
+    assert!(!errors[0].is_retryable());
+    let context = &errors[0].context;
+    assert!(context.contains(&ErrorContext::Url(
+        harness.stand_in.url("/files/broken.pdf")
+    )));
+    assert!(context.contains(&ErrorContext::Task(PathBuf::from("broken.pdf"))));
+    assert!(context.contains(&ErrorContext::Node(vec![0])));
+    assert_eq!(harness.stand_in.requests("/files/broken.pdf").len(), 1);
+
+    assert_eq!(report.errors(), 1);
+    assert!(report.sites[0].errors[0].contains("status 404"));
+    assert_eq!(kind(&finished(&events), "fine.pdf"), &MsgKind::AddedFile);
+    assert!(!harness.path("broken.pdf").exists());
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn hooks_run_after_downloads() {
+    let mut harness = Harness::new(json!([{"path": "a.pdf", "url": "/files/a.pdf"}])).await;
+    harness.stand_in.serve("/files/a.pdf", Route::file("a"));
+    harness.hook(
+        r#"test -f "$FETCHER2_FULL_PATH" && printf '%s %s' "$FETCHER2_EVENT" "$FETCHER2_REL_PATH""#,
+        10,
+    );
+
+    let (report, _) = harness.run().await;
+    assert_eq!(report.errors(), 0);
+    let hooks = &report.sites[0].hooks;
+    assert_eq!(hooks.len(), 1);
+    assert_eq!(hooks[0].full_path, harness.path("a.pdf"));
+    assert_eq!(hooks[0].exit_code, Some(0));
+    assert_eq!(hooks[0].stdout, "added a.pdf");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn failed_hooks_are_reported() {
+    let mut harness = Harness::new(json!([{"path": "a.pdf", "url": "/files/a.pdf"}])).await;
+    harness.stand_in.serve("/files/a.pdf", Route::file("a"));
+    harness.hook("echo broken >&2; exit 1", 10);
+
+    let (report, _) = harness.run().await;
+    let site = &report.sites[0];
+    assert!(site.hooks.is_empty());
+    assert_eq!(site.errors.len(), 1);
+    assert!(site.errors[0].contains("exit code Some(1)"));
+    assert!(site.errors[0].contains("broken"));
+    // the file itself is fine
+    assert_eq!(site.added, vec![PathBuf::from("a.pdf")]);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn hooks_are_killed_after_the_timeout() {
+    let mut harness = Harness::new(json!([{"path": "a.pdf", "url": "/files/a.pdf"}])).await;
+    harness.stand_in.serve("/files/a.pdf", Route::file("a"));
+    harness.hook(r#"sleep 2; touch "$FETCHER2_FULL_PATH.hooked""#, 1);
+
+    let (report, _) = harness.run().await;
+    let site = &report.sites[0];
+    assert!(site.hooks.is_empty());
+    assert_eq!(site.errors.len(), 1);
+    assert!(site.errors[0].contains("Timeout"));
+    // the killed shell never gets to the touch
+    tokio::time::sleep(Duration::from_secs(2)).await;
+    assert!(!harness.path("a.pdf.hooked").exists());
+}
+
+#[tokio::test]
+async fn server_errors_are_retried() {
+    let harness = Harness::new(json!([
+        {"path": "broken.pdf", "url": "/files/broken.pdf"},
+    ]))
+    .await;
+    harness
+        .stand_in
+        .serve("/files/broken.pdf", Route::error(500));
+
+    let (report, _) = harness.run().await;
+    assert_eq!(report.errors(), 1);
+    assert!(report.sites[0].errors[0].contains("status 500"));
+    // the first try and 4 retries
+    assert_eq!(harness.stand_in.requests("/files/broken.pdf").len(), 5);
+}
+
+#[tokio::test]
+async fn posts_are_not_retried() {
+    let stand_in = StandIn::start().await;
+    stand_in.serve("/login", Route::error(500));
+    let dir = temp_dir("e2e");
+    let session = Session::new(&dsettings(&dir)).unwrap();
+
+    let response = session
+        .post(stand_in.url("/login"))
+        .form(&[("username", "alice")])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(stand_in.requests("/login").len(), 1);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn posts_are_not_retried_on_broken_connections() {
+    let stand_in = StandIn::start().await;
+    stand_in.serve("/login", Route::hang_up());
+    let dir = temp_dir("e2e");
+    let session = Session::new(&dsettings(&dir)).unwrap();
+
+    session
+        .post(stand_in.url("/login"))
+        .form(&[("username", "alice")])
+        .send()
+        .await
+        .unwrap_err();
+    assert_eq!(stand_in.requests("/login").len(), 1);
+
+    // the same failure is retried for a GET
+    session
+        .get(stand_in.url("/login"))
+        .send()
+        .await
+        .unwrap_err();
+    assert_eq!(stand_in.requests("/login").len(), 6);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn fetcher_events_can_be_taken_after_a_run() {
+    let harness = Harness::new(json!([
+        {"path": "a.pdf", "url": "/files/a.pdf"},
+    ]))
+    .await;
+    harness.stand_in.serve("/files/a.pdf", Route::file("a"));
+    harness.template.save().await.unwrap();
+    let fetcher = Fetcher::builder(
+        (*harness.dsettings).clone(),
+        harness.dir.join("template.ron"),
+    )
+    .build()
+    .await
+    .unwrap();
+
+    // nobody listens to the first run, the stream is still there for the second
+    fetcher.run(Selection::All).await.unwrap();
+    let events = fetcher.events().unwrap();
+    assert!(fetcher.events().is_none());
+    fetcher.run(Selection::All).await.unwrap();
+    drop(fetcher);
+
+    // only the second run, a buffered first one would add a second finish
+    let events: Vec<_> = events.collect().await;
+    let finishes: Vec<_> = events
+        .iter()
+        .filter_map(|event| match &event.kind {
+            NodeEventKind::Site(SiteEventKind::Download(DownloadEventKind::Finish(msg))) => {
+                Some(msg)
+            }
+            _ => None,
+        })
+        .collect();
+    assert_eq!(finishes.len(), 1);
+    assert_eq!(finishes[0].rel_path, PathBuf::from("a.pdf"));
+    assert_eq!(finishes[0].kind, MsgKind::AlreadyExist);
+}
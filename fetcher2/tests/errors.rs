@@ -0,0 +1,95 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use reqwest::StatusCode;
+
+use fetcher2::error::{is_retryable_status, ErrorContext, TErrorContext, TErrorFast};
+use fetcher2::{Result, TError, TErrorKind};
+
+#[test]
+fn display_includes_status_and_context() {
+    let err = TError::new(TErrorKind::WrongFormat)
+        .with_status(StatusCode::NOT_FOUND)
+        .context(ErrorContext::Url("https://example.org/a.pdf".to_owned()))
+        .context(ErrorContext::Task(PathBuf::from("slides/a.pdf")))
+        .context(ErrorContext::Module("Moodle".to_owned()));
+    assert_eq!(
+        err.to_string(),
+        "Got unexpected data from server (status 404 Not Found, url https://example.org/a.pdf, \
+         task slides/a.pdf, module Moodle)"
+    );
+    assert_eq!(
+        TError::new(TErrorKind::LoginError).to_string(),
+        "Login Data was not correct"
+    );
+}
+
+#[test]
+fn context_is_added_once() {
+    let err = TError::new(TErrorKind::WrongFormat)
+        .context(ErrorContext::Node(vec![0, 2]))
+        .context(ErrorContext::Node(vec![0, 2]))
+        .context(ErrorContext::Operation("downloading"));
+    assert_eq!(
+        err.context,
+        vec![
+            ErrorContext::Node(vec![0, 2]),
+            ErrorContext::Operation("downloading")
+        ]
+    );
+}
+
+#[test]
+fn results_and_options_get_context() {
+    let result: Result<()> = Err::<(), _>(std::io::Error::from(std::io::ErrorKind::NotFound))
+        .with_context(|| ErrorContext::Path(PathBuf::from("a.txt")));
+    let err = result.unwrap_err();
+    assert!(matches!(err.kind, TErrorKind::FileError(_)));
+    assert_eq!(
+        err.context,
+        vec![ErrorContext::Path(PathBuf::from("a.txt"))]
+    );
+
+    let err = None::<()>.wrong_format("course name").unwrap_err();
+    assert!(matches!(err.kind, TErrorKind::WrongFormat));
+    assert_eq!(err.context, vec![ErrorContext::Parse("course name")]);
+}
+
+#[test]
+fn status_decides_about_retries() {
+    for status in [
+        StatusCode::TOO_MANY_REQUESTS,
+        StatusCode::BAD_GATEWAY,
+        StatusCode::SERVICE_UNAVAILABLE,
+    ] {
+        assert!(is_retryable_status(status));
+        assert!(TError::new(TErrorKind::WrongFormat)
+            .with_status(status)
+            .is_retryable());
+    }
+    for status in [
+        StatusCode::NOT_FOUND,
+        StatusCode::FORBIDDEN,
+        StatusCode::UNAUTHORIZED,
+    ] {
+        assert!(!is_retryable_status(status));
+        assert!(!TError::new(TErrorKind::WrongFormat)
+            .with_status(status)
+            .is_retryable());
+    }
+}
+
+#[tokio::test]
+async fn temporary_failures_are_retryable() {
+    let elapsed = tokio::time::timeout(Duration::from_millis(1), std::future::pending::<()>())
+        .await
+        .unwrap_err();
+    assert!(TError::from(elapsed).is_retryable());
+    let reset = std::io::Error::from(std::io::ErrorKind::ConnectionReset);
+    assert!(TError::from(reset).is_retryable());
+
+    let not_found = std::io::Error::from(std::io::ErrorKind::NotFound);
+    assert!(!TError::from(not_found).is_retryable());
+    assert!(!TError::new(TErrorKind::WrongFormat).is_retryable());
+    assert!(!TError::new(TErrorKind::LoginError).is_retryable());
+}
@@ -0,0 +1,126 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use fetcher2::template::node_type::extract::{enclosed_path, extract, ArchiveKind};
+use fetcher2::TErrorKind;
+
+use support::temp_dir;
+
+mod support;
+
+fn write_zip(path: &Path, entries: &[(&str, &str)]) {
+    let mut zip = zip::ZipWriter::new(File::create(path).unwrap());
+    for (name, content) in entries {
+        zip.start_file(*name, Default::default()).unwrap();
+        zip.write_all(content.as_bytes()).unwrap();
+    }
+    zip.finish().unwrap();
+}
+
+// tar::Header::set_path refuses "..", so the name is written by hand
+fn write_tar(path: &Path, entries: &[(&str, &str)]) {
+    let mut tar = tar::Builder::new(File::create(path).unwrap());
+    for (name, content) in entries {
+        let mut header = tar::Header::new_old();
+        header.as_old_mut().name[..name.len()].copy_from_slice(name.as_bytes());
+        header.set_size(content.len() as u64);
+        header.set_entry_type(tar::EntryType::Regular);
+        header.set_cksum();
+        tar.append(&header, content.as_bytes()).unwrap();
+    }
+    tar.finish().unwrap();
+}
+
+#[test]
+fn paths_stay_inside_the_folder() {
+    assert_eq!(
+        enclosed_path(Path::new("slides/week1.pdf")),
+        Some(PathBuf::from("slides/week1.pdf"))
+    );
+    assert_eq!(
+        enclosed_path(Path::new("./slides/./week1.pdf")),
+        Some(PathBuf::from("slides/week1.pdf"))
+    );
+    assert_eq!(enclosed_path(Path::new("./")), None);
+    assert_eq!(enclosed_path(Path::new("")), None);
+
+    assert_eq!(enclosed_path(Path::new("../evil.txt")), None);
+    assert_eq!(enclosed_path(Path::new("slides/../../evil.txt")), None);
+    // even if it would end up inside again
+    assert_eq!(enclosed_path(Path::new("slides/../week1.pdf")), None);
+    assert_eq!(enclosed_path(Path::new("/etc/passwd")), None);
+}
+
+#[tokio::test]
+async fn archives_are_extracted() {
+    let dir = temp_dir("extract");
+    let archive = dir.join("exercise.zip");
+    write_zip(&archive, &[("a.txt", "a"), ("./sub/b.txt", "b")]);
+
+    let target = ArchiveKind::Zip.target_dir(&archive);
+    let mut files = extract(archive, ArchiveKind::Zip, target.clone(), false)
+        .await
+        .unwrap();
+    files.sort();
+    assert_eq!(files.len(), 2);
+    assert_eq!(files[0].0, target.join("a.txt"));
+    assert_eq!(files[1].0, target.join("sub/b.txt"));
+    assert_eq!(
+        std::fs::read_to_string(target.join("sub/b.txt")).unwrap(),
+        "b"
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn malicious_archives_are_rejected() {
+    let dir = temp_dir("extract");
+    let zip = dir.join("sub/evil.zip");
+    std::fs::create_dir_all(zip.parent().unwrap()).unwrap();
+    write_zip(&zip, &[("fine.txt", "fine"), ("../../evil.txt", "evil")]);
+    let tar = dir.join("sub/evil.tar");
+    write_tar(&tar, &[("fine.txt", "fine"), ("../../evil.txt", "evil")]);
+
+    for (archive, kind) in [(zip, ArchiveKind::Zip), (tar, ArchiveKind::Tar)] {
+        let target = kind.target_dir(&archive);
+        let err = extract(archive, kind, target.clone(), false)
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(&err.kind, TErrorKind::UnsafeArchivePath(path) if path == "../../evil.txt")
+        );
+        // nothing is left behind, not even the files before the bad one
+        assert!(!target.exists());
+    }
+    assert!(!dir.join("evil.txt").exists());
+    assert!(!dir.parent().unwrap().join("evil.txt").exists());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn foreign_folders_are_not_replaced() {
+    let dir = temp_dir("extract");
+    let archive = dir.join("exercise.zip");
+    write_zip(&archive, &[("a.txt", "a")]);
+    let target = ArchiveKind::Zip.target_dir(&archive);
+    std::fs::create_dir_all(&target).unwrap();
+    std::fs::write(target.join("mine.txt"), "mine").unwrap();
+
+    let err = extract(archive.clone(), ArchiveKind::Zip, target.clone(), false)
+        .await
+        .unwrap_err();
+    assert!(matches!(&err.kind, TErrorKind::ExtractTargetExists(path) if path == &target));
+    assert!(target.join("mine.txt").exists());
+
+    // a folder from a previous extraction is replaced
+    extract(archive, ArchiveKind::Zip, target.clone(), true)
+        .await
+        .unwrap();
+    assert!(!target.join("mine.txt").exists());
+    assert!(target.join("a.txt").exists());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
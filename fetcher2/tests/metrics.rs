@@ -0,0 +1,116 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use chrono::Utc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use uuid::Uuid;
+
+use fetcher2::metrics::{metrics, Metrics, MetricsServer};
+use fetcher2::template::report::{RunReport, SiteReport};
+
+fn site(module: &str) -> SiteReport {
+    let started = Utc::now();
+    SiteReport {
+        index: vec![0],
+        module: module.to_owned(),
+        path: None,
+        added: vec![PathBuf::from("a.pdf"), PathBuf::from("b.pdf")],
+        replaced: Vec::new(),
+        unchanged: 3,
+        forbidden: 0,
+        errors: vec!["Client Error".to_owned()],
+        warnings: Vec::new(),
+        rate_limit_wait_secs: 0.,
+        bytes: 1024,
+        hooks: Vec::new(),
+        extracted: Vec::new(),
+        started: Some(started),
+        finished: Some(started + chrono::Duration::seconds(2)),
+    }
+}
+
+fn report(sites: Vec<SiteReport>) -> RunReport {
+    RunReport {
+        run_id: Uuid::new_v4(),
+        started: Utc::now(),
+        finished: Utc::now(),
+        sites,
+    }
+}
+
+#[test]
+fn renders_prometheus_text() {
+    let metrics = Metrics::new();
+    metrics.record_request("example.org", Some(200), Duration::from_millis(200));
+    metrics.record_request("example.org", None, Duration::from_secs(40));
+    metrics.record_retry("example.org");
+    metrics.record_run(&report(vec![site("Moodle"), site("Odd \"name\"")]));
+
+    let text = metrics.render();
+    for line in [
+        "# TYPE fetcher2_http_requests_total counter",
+        "fetcher2_http_requests_total{host=\"example.org\",status=\"200\"} 1",
+        "fetcher2_http_requests_total{host=\"example.org\",status=\"error\"} 1",
+        "fetcher2_http_retries_total{host=\"example.org\"} 1",
+        "# TYPE fetcher2_http_request_duration_seconds histogram",
+        "fetcher2_http_request_duration_seconds_bucket{host=\"example.org\",le=\"0.1\"} 0",
+        "fetcher2_http_request_duration_seconds_bucket{host=\"example.org\",le=\"0.25\"} 1",
+        "fetcher2_http_request_duration_seconds_bucket{host=\"example.org\",le=\"+Inf\"} 2",
+        "fetcher2_http_request_duration_seconds_count{host=\"example.org\"} 2",
+        "fetcher2_files_total{module=\"Moodle\",result=\"added\"} 2",
+        "fetcher2_files_total{module=\"Moodle\",result=\"unchanged\"} 3",
+        "fetcher2_downloaded_bytes_total{module=\"Moodle\"} 1024",
+        "fetcher2_errors_total{module=\"Moodle\"} 1",
+        "fetcher2_errors_total{module=\"Odd \\\"name\\\"\"} 1",
+        "fetcher2_site_duration_seconds_bucket{module=\"Moodle\",le=\"2.5\"} 1",
+        "fetcher2_runs_total 1",
+    ] {
+        assert!(
+            text.lines().any(|rendered| rendered == line),
+            "{} missing in\n{}",
+            line,
+            text
+        );
+    }
+    assert!(text.contains("fetcher2_last_run_timestamp_seconds "));
+}
+
+#[tokio::test]
+async fn writes_textfile_in_one_step() {
+    let dir = std::env::temp_dir().join(format!("fetcher2-metrics-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("fetcher2.prom");
+
+    let metrics = Metrics::new();
+    metrics.record_run(&report(vec![site("Polybox")]));
+    metrics.write_textfile(&path).await.unwrap();
+
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), metrics.render());
+    // node_exporter only reads *.prom, the temporary file must be gone
+    let files: Vec<_> = std::fs::read_dir(&dir).unwrap().collect();
+    assert_eq!(files.len(), 1);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn serves_metrics_on_a_port() {
+    let host = format!("{}.example.org", Uuid::new_v4());
+    metrics().record_request(&host, Some(404), Duration::from_millis(10));
+    let server = MetricsServer::start(0).await.unwrap();
+
+    let mut stream = TcpStream::connect(server.addr()).await.unwrap();
+    stream
+        .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.contains(&format!(
+        "fetcher2_http_requests_total{{host=\"{}\",status=\"404\"}} 1",
+        host
+    )));
+}
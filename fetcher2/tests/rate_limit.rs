@@ -0,0 +1,92 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use fetcher2::rate_limit::{host_matches, Bucket, RateLimiter};
+use fetcher2::settings::RateLimit;
+
+fn secs(secs: f64) -> Duration {
+    Duration::from_secs_f64(secs)
+}
+
+#[test]
+fn bursts_then_queues() {
+    let start = Instant::now();
+    let mut bucket = Bucket::new(RateLimit::new(2., 3), start);
+    for _ in 0..3 {
+        assert_eq!(bucket.take(start), Duration::ZERO);
+    }
+    // the tokens go negative, every request waits behind the one before it
+    assert_eq!(bucket.take(start), secs(0.5));
+    assert_eq!(bucket.take(start), secs(1.));
+}
+
+#[test]
+fn refills_up_to_the_burst() {
+    let start = Instant::now();
+    let mut bucket = Bucket::new(RateLimit::new(2., 3), start);
+    for _ in 0..5 {
+        bucket.take(start);
+    }
+    // -2 tokens, 2 seconds later there are 2 again
+    let later = start + secs(2.);
+    assert_eq!(bucket.take(later), Duration::ZERO);
+    assert_eq!(bucket.take(later), Duration::ZERO);
+    assert_eq!(bucket.take(later), secs(0.5));
+
+    let mut bucket = Bucket::new(RateLimit::new(2., 3), start);
+    let much_later = start + secs(100.);
+    for _ in 0..3 {
+        assert_eq!(bucket.take(much_later), Duration::ZERO);
+    }
+    assert_eq!(bucket.take(much_later), secs(0.5));
+}
+
+#[test]
+fn domains_match_their_subdomains() {
+    assert!(host_matches("example.org", "example.org"));
+    assert!(host_matches("moodle.example.org", "example.org"));
+    assert!(host_matches("Moodle.Example.org", "example.ORG"));
+    assert!(host_matches("moodle.example.org", " .example.org"));
+    assert!(!host_matches("notexample.org", "example.org"));
+    assert!(!host_matches("example.org", "moodle.example.org"));
+    assert!(!host_matches("example.org.evil.com", "example.org"));
+}
+
+#[test]
+fn configured_limits_replace_the_fallback() {
+    let limiter = RateLimiter::new(HashMap::from([
+        ("example.org".to_owned(), RateLimit::new(1., 1)),
+        ("files.example.org".to_owned(), RateLimit::new(5., 1)),
+        ("off.example.org".to_owned(), RateLimit::new(0., 1)),
+    ]));
+    let fallback = Some(RateLimit::new(10., 4));
+
+    assert_eq!(
+        limiter.limit("a.example.org", fallback),
+        Some(RateLimit::new(1., 1))
+    );
+    assert_eq!(
+        limiter.limit("cdn.files.example.org", fallback),
+        Some(RateLimit::new(5., 1))
+    );
+    assert_eq!(limiter.limit("other.org", fallback), fallback);
+    assert_eq!(limiter.limit("other.org", None), None);
+    assert_eq!(limiter.limit("off.example.org", fallback), None);
+}
+
+#[tokio::test]
+async fn subdomains_share_the_bucket_of_their_domain() {
+    let limiter = RateLimiter::new(HashMap::from([(
+        "example.org".to_owned(),
+        RateLimit::new(20., 1),
+    )]));
+
+    assert_eq!(limiter.wait("a.example.org", None).await, Duration::ZERO);
+    assert!(!limiter.wait("b.example.org", None).await.is_zero());
+    // hosts with a fallback limit get a bucket each
+    let fallback = Some(RateLimit::new(20., 1));
+    assert_eq!(limiter.wait("a.other.org", fallback).await, Duration::ZERO);
+    assert_eq!(limiter.wait("b.other.org", fallback).await, Duration::ZERO);
+}
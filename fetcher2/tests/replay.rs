@@ -0,0 +1,186 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use url::Url;
+
+use fetcher2::replay::{Exchange, FixtureBody};
+use fetcher2::session::Session;
+use fetcher2::settings::DownloadSettings;
+use fetcher2::site_modules::{share_login, Mode, Module, Polybox};
+use fetcher2::TErrorKind;
+
+use support::{temp_dir, Route, StandIn};
+
+mod support;
+
+fn settings(username: &str, password: &str) -> DownloadSettings {
+    ron::de::from_str(&format!(
+        r#"(
+            username: Some("{}"),
+            password: Some("{}"),
+            save_path: "/tmp",
+            download_args: (
+                extensions: (mode: Forbidden, inner: []),
+                keep_old_files: true,
+            ),
+            force: false,
+            cookies: (persist: false),
+        )"#,
+        username, password
+    ))
+    .unwrap()
+}
+
+fn fixture_files(dir: &Path) -> Vec<String> {
+    let mut paths: Vec<_> = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    paths.sort();
+    paths
+        .iter()
+        .map(|path| std::fs::read_to_string(path).unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn records_without_credentials_and_replays_offline() {
+    let dir = temp_dir("record");
+    // greets the user and sets a cookie
+    let stand_in = StandIn::start().await;
+    stand_in.serve(
+        "/login",
+        Route::file("Welcome alice").cookie("session=secret-session"),
+    );
+    let url = stand_in.url("/login");
+
+    let alice = settings("alice", "s3cret pw");
+    let session = Session::recording(&alice, &dir).unwrap();
+    let text = session
+        .post(&url)
+        .form(&[("username", "alice"), ("password", "s3cret pw")])
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert_eq!(text, "Welcome alice");
+
+    let files = fixture_files(&dir);
+    assert_eq!(files.len(), 1);
+    assert!(!files[0].contains("s3cret") && !files[0].contains("alice"));
+    let exchange: Exchange = serde_json::from_str(&files[0]).unwrap();
+    assert_eq!(
+        exchange.request_body.as_deref(),
+        Some("username=<username>&password=<password>")
+    );
+    assert_eq!(
+        exchange.body,
+        FixtureBody::Text("Welcome <username>".to_owned())
+    );
+    assert!(exchange
+        .headers
+        .iter()
+        .all(|(name, _)| name != "set-cookie"));
+
+    // this never reaches the network
+    let bob = settings("bob", "hunter2");
+    let session = Session::replaying(&bob, &dir).unwrap();
+    let response = session
+        .post(&url)
+        .form(&[("username", "bob"), ("password", "hunter2")])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.text().await.unwrap(), "Welcome <username>");
+
+    let err = session.get(&url).send().await.err().unwrap();
+    assert!(matches!(err.kind, TErrorKind::MissingFixture(_)));
+    assert_eq!(stand_in.requests("/login").len(), 1);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn polybox_shared_folder_from_fixture() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/polybox_shared");
+    let dsettings = settings("polybox-user", "polybox-pw");
+    let session = Session::replaying(&dsettings, &dir).unwrap();
+    let module = Module::Polybox(Polybox {
+        id: "AbCdEf123".to_owned(),
+        mode: Mode::Shared(None),
+    });
+
+    let (sender, mut receiver) = tokio::sync::mpsc::channel(16);
+    module
+        .fetch_urls(session, sender, Arc::new(dsettings))
+        .await
+        .unwrap();
+
+    let mut tasks = Vec::new();
+    while let Some(task) = receiver.recv().await {
+        tasks.push(task);
+    }
+    let paths: Vec<_> = tasks.iter().map(|task| task.path.clone()).collect();
+    assert_eq!(
+        paths,
+        [
+            PathBuf::from("Exercises/Sheet 1.pdf"),
+            PathBuf::from("Lecture.pdf")
+        ]
+    );
+    assert_eq!(
+        tasks[0].url.as_str(),
+        "https://polybox.ethz.ch/public.php/webdav/Exercises/Sheet%201.pdf"
+    );
+    assert_eq!(
+        tasks[1].checksum.as_deref(),
+        Some("SHA1:de9f2c7fd25e1b3afad3e85a0bd17d9b100db4b3")
+    );
+    assert_eq!(tasks[1].basic_auth, Some(("AbCdEf123".to_owned(), None)));
+}
+
+// the login of Mode::Shared(Some(password))
+#[tokio::test]
+async fn polybox_share_password_is_not_recorded() {
+    let stand_in = StandIn::start().await;
+    let path = "/index.php/s/AbCdEf123/authenticate";
+    stand_in.serve(
+        path,
+        Route::file(
+            r#"<html><head data-requesttoken="tok-42">
+            <input type="hidden" name="requesttoken" value="tok-42"/></html>"#,
+        ),
+    );
+    let url = Url::parse(&stand_in.url(path)).unwrap();
+    let dir = temp_dir("record");
+
+    let session = Session::recording(&settings("alice", "alice-pw"), &dir).unwrap();
+    share_login(&session, url.clone(), "share-pw")
+        .await
+        .unwrap();
+    assert_eq!(
+        stand_in.requests(path)[1].body,
+        "requesttoken=tok-42&password=share-pw"
+    );
+
+    let files = fixture_files(&dir);
+    assert_eq!(files.len(), 2);
+    for file in &files {
+        assert!(!file.contains("share-pw") && !file.contains("tok-42"));
+    }
+    let exchange: Exchange = serde_json::from_str(&files[1]).unwrap();
+    assert_eq!(
+        exchange.request_body.as_deref(),
+        Some("requesttoken=<requesttoken>&password=<password>")
+    );
+
+    // another share password matches the same fixtures
+    let session = Session::replaying(&settings("bob", "bob-pw"), &dir).unwrap();
+    share_login(&session, url, "other-pw").await.unwrap();
+    assert_eq!(stand_in.requests(path).len(), 2);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
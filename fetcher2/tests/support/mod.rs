@@ -0,0 +1,291 @@
+// every test binary uses a different part of the support code
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use uuid::Uuid;
+
+use fetcher2::settings::DownloadSettings;
+
+// a fresh, existing directory, removing it is left to the test
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("fetcher2-{}-{}", name, Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+// exe files are forbidden, cookies stay in memory, retries follow right away
+pub fn dsettings(save_path: &Path) -> DownloadSettings {
+    ron::de::from_str(&format!(
+        r#"(
+            save_path: "{}",
+            download_args: (
+                extensions: (mode: Forbidden, inner: ["exe"]),
+                keep_old_files: true,
+            ),
+            force: false,
+            retry_delay: 10,
+            cookies: (persist: false),
+        )"#,
+        save_path.display()
+    ))
+    .unwrap()
+}
+
+// What the stand-in answers for one path
+#[derive(Debug, Clone)]
+pub struct Route {
+    pub status: u16,
+    pub body: Vec<u8>,
+    pub etag: Option<String>,
+    pub content_type: Option<String>,
+    pub content_disposition: Option<String>,
+    pub set_cookie: Option<String>,
+    // the connection is closed without an answer
+    pub hang_up: bool,
+    // the body is sent in this many parts with chunk_delay in between
+    pub chunks: usize,
+    pub chunk_delay: Duration,
+}
+
+impl Route {
+    pub fn file(body: impl Into<Vec<u8>>) -> Self {
+        Self {
+            status: 200,
+            body: body.into(),
+            etag: None,
+            content_type: None,
+            content_disposition: None,
+            set_cookie: None,
+            hang_up: false,
+            chunks: 1,
+            chunk_delay: Duration::ZERO,
+        }
+    }
+
+    pub fn json(body: &serde_json::Value) -> Self {
+        Self::file(body.to_string()).content_type("application/json")
+    }
+
+    pub fn error(status: u16) -> Self {
+        Self {
+            status,
+            ..Self::file("error")
+        }
+    }
+
+    // requests with a matching If-None-Match get a 304
+    pub fn hang_up() -> Self {
+        Self {
+            hang_up: true,
+            ..Self::file("")
+        }
+    }
+
+    pub fn etag(mut self, etag: &str) -> Self {
+        self.etag = Some(format!("\"{}\"", etag));
+        self
+    }
+
+    pub fn content_type(mut self, content_type: &str) -> Self {
+        self.content_type = Some(content_type.to_owned());
+        self
+    }
+
+    pub fn attachment(mut self, file_name: &str) -> Self {
+        self.content_disposition = Some(format!("attachment; filename=\"{}\"", file_name));
+        self
+    }
+
+    pub fn cookie(mut self, cookie: &str) -> Self {
+        self.set_cookie = Some(cookie.to_owned());
+        self
+    }
+
+    pub fn slow(mut self, chunks: usize, chunk_delay: Duration) -> Self {
+        self.chunks = chunks.max(1);
+        self.chunk_delay = chunk_delay;
+        self
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl RecordedRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+// A local http server with routes that can be changed between runs.
+// Unknown paths get a 404, every request is recorded.
+#[derive(Clone)]
+pub struct StandIn {
+    addr: SocketAddr,
+    // answered in order, the last one stays
+    routes: Arc<Mutex<HashMap<String, Vec<Route>>>>,
+    requests: Arc<Mutex<Vec<RecordedRequest>>>,
+}
+
+impl StandIn {
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let stand_in = Self {
+            addr: listener.local_addr().unwrap(),
+            routes: Arc::default(),
+            requests: Arc::default(),
+        };
+        let server = stand_in.clone();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(server.clone().handle(stream));
+            }
+        });
+        stand_in
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{}", self.addr, path)
+    }
+
+    pub fn serve(&self, path: &str, route: Route) {
+        self.serve_in_order(path, vec![route]);
+    }
+
+    // e.g. an error first and then the file
+    pub fn serve_in_order(&self, path: &str, routes: Vec<Route>) {
+        assert!(!routes.is_empty());
+        self.routes.lock().unwrap().insert(path.to_owned(), routes);
+    }
+
+    pub fn requests(&self, path: &str) -> Vec<RecordedRequest> {
+        self.requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|request| request.path == path)
+            .cloned()
+            .collect()
+    }
+
+    async fn handle(self, mut stream: TcpStream) {
+        let request = match read_request(&mut stream).await {
+            Some(request) => request,
+            None => return,
+        };
+        let route = match self.routes.lock().unwrap().get_mut(&request.path) {
+            Some(routes) if routes.len() > 1 => Some(routes.remove(0)),
+            Some(routes) => routes.first().cloned(),
+            None => None,
+        };
+        let if_none_match = request.header("If-None-Match").map(str::to_owned);
+        self.requests.lock().unwrap().push(request);
+
+        let route = match route {
+            Some(route) => route,
+            None => Route::error(404),
+        };
+        if route.hang_up {
+            return;
+        }
+        let not_modified = route.etag.is_some() && route.etag == if_none_match;
+        let (status, body) = if not_modified {
+            (304, &[][..])
+        } else {
+            (route.status, &route.body[..])
+        };
+
+        let mut head = format!(
+            "HTTP/1.1 {} Stand-In\r\nContent-Length: {}\r\nConnection: close\r\n",
+            status,
+            body.len()
+        );
+        for (name, value) in [
+            ("ETag", &route.etag),
+            ("Content-Type", &route.content_type),
+            ("Content-Disposition", &route.content_disposition),
+            ("Set-Cookie", &route.set_cookie),
+        ] {
+            if let Some(value) = value {
+                head.push_str(&format!("{}: {}\r\n", name, value));
+            }
+        }
+        head.push_str("\r\n");
+        if stream.write_all(head.as_bytes()).await.is_err() {
+            return;
+        }
+
+        let chunk_size = (body.len() / route.chunks).max(1);
+        for (i, chunk) in body.chunks(chunk_size).enumerate() {
+            if i > 0 {
+                tokio::time::sleep(route.chunk_delay).await;
+            }
+            if stream.write_all(chunk).await.is_err() {
+                return;
+            }
+            let _ = stream.flush().await;
+        }
+    }
+}
+
+async fn read_request(stream: &mut TcpStream) -> Option<RecordedRequest> {
+    let mut data = Vec::new();
+    let mut buffer = [0u8; 4096];
+    let (head, body_start) = loop {
+        let n = stream.read(&mut buffer).await.ok()?;
+        if n == 0 {
+            return None;
+        }
+        data.extend_from_slice(&buffer[..n]);
+        if let Some(end) = data.windows(4).position(|window| window == b"\r\n\r\n") {
+            break (String::from_utf8_lossy(&data[..end]).into_owned(), end + 4);
+        }
+    };
+
+    let mut lines = head.lines();
+    let mut request_line = lines.next()?.split_whitespace();
+    let method = request_line.next()?.to_owned();
+    let path = request_line.next()?.to_owned();
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            Some((name.trim().to_owned(), value.trim().to_owned()))
+        })
+        .collect();
+
+    let content_length = headers
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("Content-Length"))
+        .and_then(|(_, value)| value.parse().ok())
+        .unwrap_or(0);
+    let mut body = data.split_off(body_start);
+    while body.len() < content_length {
+        let n = stream.read(&mut buffer).await.ok()?;
+        if n == 0 {
+            return None;
+        }
+        body.extend_from_slice(&buffer[..n]);
+    }
+    Some(RecordedRequest {
+        method,
+        path,
+        headers,
+        body: String::from_utf8_lossy(&body).into_owned(),
+    })
+}
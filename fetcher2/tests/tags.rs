@@ -0,0 +1,64 @@
+use fetcher2::template::tags::TagExpr;
+use fetcher2::TErrorKind;
+
+use TagExpr::{And, Not, Or};
+
+fn tag(name: &str) -> Box<TagExpr> {
+    Box::new(TagExpr::Tag(name.to_owned()))
+}
+
+fn parse(expr: &str) -> TagExpr {
+    expr.parse().unwrap()
+}
+
+fn tags(tags: &[&str]) -> im::HashSet<String> {
+    tags.iter().map(|tag| tag.to_string()).collect()
+}
+
+#[test]
+fn not_binds_tighter_than_and_than_or() {
+    assert_eq!(
+        parse("a | b & !c"),
+        Or(tag("a"), Box::new(And(tag("b"), Box::new(Not(tag("c"))))))
+    );
+    assert_eq!(
+        parse("!a & b | c"),
+        Or(Box::new(And(Box::new(Not(tag("a"))), tag("b"))), tag("c"))
+    );
+    // left associative
+    assert_eq!(
+        parse("a & b & c"),
+        And(Box::new(And(tag("a"), tag("b"))), tag("c"))
+    );
+    assert_eq!(parse("!!a"), Not(Box::new(Not(tag("a")))));
+}
+
+#[test]
+fn parentheses_group() {
+    assert_eq!(
+        parse("(a | b) & c"),
+        And(Box::new(Or(tag("a"), tag("b"))), tag("c"))
+    );
+    assert_eq!(parse("!(a & b)"), Not(Box::new(And(tag("a"), tag("b")))));
+    assert_eq!(parse(" ( ( exam-2023.v1 ) ) "), *tag("exam-2023.v1"));
+
+    let expr = parse("!(slides | videos) & hs22");
+    assert!(expr.matches(&tags(&["hs22", "exercises"])));
+    assert!(!expr.matches(&tags(&["hs22", "videos"])));
+    assert!(!expr.matches(&tags(&["exercises"])));
+}
+
+#[test]
+fn malformed_expressions_are_rejected() {
+    for expr in [
+        "", "a &", "& a", "a b", "(a | b", "a | b)", "()", "!", "a && b", "a, b", "#a",
+    ] {
+        let err = expr.parse::<TagExpr>().unwrap_err();
+        assert!(
+            matches!(&err.kind, TErrorKind::TagExpression(msg) if msg.contains(&format!("{:?}", expr))),
+            "{:?} gave {:?}",
+            expr,
+            err.kind
+        );
+    }
+}
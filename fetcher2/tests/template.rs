@@ -0,0 +1,459 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde_json::json;
+use uuid::Uuid;
+
+use fetcher2::error::TErrorKind;
+use fetcher2::site_modules::{Listing, Module};
+use fetcher2::template::node_type::{Folder, NodeType, Site, SiteStorage};
+use fetcher2::template::nodes::node::{PathRefresh, RawNode, Status};
+use fetcher2::template::nodes::root::RawRootNode;
+use fetcher2::template::tags::TagExpr;
+use fetcher2::template::{format, state, Template, UnPrepared};
+
+use support::{dsettings, temp_dir, Route, StandIn};
+
+mod support;
+
+fn folder(name: &str, children: Vec<RawNode>) -> RawNode {
+    RawNode {
+        ty: NodeType::Folder(Folder {
+            name: name.to_owned(),
+            overrides: Default::default(),
+        }),
+        children,
+        cached_path_segment: None,
+        cached_at: None,
+        path_refresh: PathRefresh::Never,
+        enabled: true,
+        tags: im::HashSet::new(),
+    }
+}
+
+fn site(url: String, name: &str) -> RawNode {
+    let site = Site {
+        id: Uuid::new_v4(),
+        module: Module::Listing(Listing {
+            url,
+            name: name.to_owned(),
+        }),
+        storage: Arc::new(SiteStorage::new()),
+        download_args: None,
+        insecure: false,
+        headers: HashMap::new(),
+    };
+    RawNode {
+        ty: NodeType::Site(Arc::new(site)),
+        ..folder(name, Vec::new())
+    }
+}
+
+fn with_credentials(mut node: RawNode, username: &str, password: &str) -> RawNode {
+    if let NodeType::Folder(folder) = &mut node.ty {
+        folder.overrides.username = Some(username.to_owned());
+        folder.overrides.password = Some(password.to_owned());
+    }
+    node
+}
+
+fn cached(mut node: RawNode, segment: &str) -> RawNode {
+    node.cached_path_segment = Some(PathBuf::from(segment));
+    node
+}
+
+fn with_id(mut node: RawNode, id: Uuid) -> RawNode {
+    if let NodeType::Site(site) = &mut node.ty {
+        Arc::make_mut(site).id = id;
+    }
+    node
+}
+
+fn tagged(mut node: RawNode, tags: &[&str]) -> RawNode {
+    node.tags = tags.iter().map(|tag| tag.to_string()).collect();
+    node
+}
+
+fn template(dir: &Path, children: Vec<RawNode>) -> Template<UnPrepared> {
+    Template::new(RawRootNode { children }, dir.join("template.ron")).0
+}
+
+#[tokio::test]
+async fn renamed_folders_are_moved() {
+    let dir = temp_dir("template");
+    std::fs::create_dir_all(dir.join("Old/Sub")).unwrap();
+    std::fs::write(dir.join("Old/Sub/a.txt"), "a").unwrap();
+
+    // the edit window keeps the cached segment when a folder is renamed
+    let renamed = cached(
+        folder("New", vec![cached(folder("Sub", vec![]), "Sub")]),
+        "Old",
+    );
+    let template = template(&dir, vec![renamed])
+        .prepare(Arc::new(dsettings(&dir)))
+        .await
+        .unwrap();
+
+    assert_eq!(
+        std::fs::read_to_string(dir.join("New/Sub/a.txt")).unwrap(),
+        "a"
+    );
+    assert!(!dir.join("Old").exists());
+    let node = &template.root.children[0];
+    assert_eq!(node.cached_path_segment, Some(PathBuf::from("New")));
+    assert_eq!(node.children[0].path, Some(PathBuf::from("New/Sub")));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn folders_log_in_with_their_own_credentials() {
+    let stand_in = StandIn::start().await;
+    stand_in.serve("/index.json", Route::json(&json!([])));
+    stand_in.serve("/login", Route::file("welcome"));
+    let dir = temp_dir("template");
+    let index = stand_in.url("/index.json");
+
+    let template = template(
+        &dir,
+        vec![
+            with_credentials(
+                folder(
+                    "Alice",
+                    vec![site(index.clone(), "A1"), site(index.clone(), "A2")],
+                ),
+                "alice",
+                "a-secret",
+            ),
+            with_credentials(
+                folder("Bob", vec![site(index.clone(), "B")]),
+                "bob",
+                "b-secret",
+            ),
+            // no credentials at all, nothing to log in with
+            folder("Guest", vec![site(index, "C")]),
+        ],
+    );
+    let dsettings = Arc::new(dsettings(&dir));
+    let template = template.prepare(Arc::clone(&dsettings)).await.unwrap();
+
+    // the sites of a folder share the login of their credentials
+    let mut logins: Vec<_> = stand_in
+        .requests("/login")
+        .into_iter()
+        .map(|request| request.body)
+        .collect();
+    logins.sort();
+    assert_eq!(
+        logins,
+        vec![
+            "username=alice&password=a-secret",
+            "username=bob&password=b-secret"
+        ]
+    );
+
+    // every run starts with a new session
+    let report = template.run_root(dsettings).await.unwrap();
+    assert_eq!(report.errors(), 0);
+    assert_eq!(stand_in.requests("/login").len(), 4);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn task_headers_beat_site_headers_beat_global_ones() {
+    let stand_in = StandIn::start().await;
+    stand_in.serve(
+        "/index.json",
+        Route::json(&json!([
+            {"path": "a.pdf", "url": "/a.pdf"},
+            {"path": "b.pdf", "url": "/b.pdf", "headers": {"X-Level": "task"}},
+        ])),
+    );
+    stand_in.serve("/a.pdf", Route::file("a"));
+    stand_in.serve("/b.pdf", Route::file("b"));
+    let dir = temp_dir("template");
+    let mut node = site(stand_in.url("/index.json"), "Course");
+    if let NodeType::Site(site) = &mut node.ty {
+        Arc::make_mut(site).headers = HashMap::from([("X-Level".to_owned(), "site".to_owned())]);
+    }
+    let template = template(&dir, vec![node]);
+    let mut dsettings = dsettings(&dir);
+    dsettings.headers = HashMap::from([
+        ("X-Level".to_owned(), "global".to_owned()),
+        ("X-Global".to_owned(), "yes".to_owned()),
+    ]);
+    let dsettings = Arc::new(dsettings);
+
+    let template = template.prepare(Arc::clone(&dsettings)).await.unwrap();
+    let report = template.run_root(dsettings).await.unwrap();
+    assert_eq!(report.errors(), 0);
+
+    let index = &stand_in.requests("/index.json")[0];
+    assert_eq!(index.header("X-Level"), Some("site"));
+    assert_eq!(index.header("X-Global"), Some("yes"));
+    // without a configured one the user agent names the program
+    assert!(index.header("User-Agent").unwrap().starts_with("fetcher2/"));
+    assert_eq!(
+        stand_in.requests("/a.pdf")[0].header("X-Level"),
+        Some("site")
+    );
+    let b = &stand_in.requests("/b.pdf")[0];
+    assert_eq!(b.header("X-Level"), Some("task"));
+    assert_eq!(b.header("X-Global"), Some("yes"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn tags_are_inherited() {
+    let dir = temp_dir("template");
+    let template = template(
+        &dir,
+        vec![
+            tagged(
+                folder(
+                    "Course",
+                    vec![
+                        tagged(site("/a".to_owned(), "A"), &["slides"]),
+                        site("/b".to_owned(), "B"),
+                    ],
+                ),
+                &["hs22"],
+            ),
+            folder(
+                "Other",
+                vec![tagged(site("/c".to_owned(), "C"), &["slides"])],
+            ),
+        ],
+    );
+    let select = |expr: &str| {
+        let mut selected: Vec<_> = template
+            .select(&expr.parse::<TagExpr>().unwrap())
+            .into_iter()
+            .map(|idx| idx.into_iter().collect::<Vec<_>>())
+            .collect();
+        selected.sort();
+        selected
+    };
+
+    // folders are never selected, running them would run all of their sites
+    assert_eq!(select("hs22"), vec![vec![0, 0], vec![0, 1]]);
+    assert_eq!(select("slides"), vec![vec![0, 0], vec![1, 0]]);
+    assert_eq!(select("hs22 & slides"), vec![vec![0, 0]]);
+    assert_eq!(select("hs22 & !slides"), vec![vec![0, 1]]);
+    assert_eq!(select("!hs22"), vec![vec![1, 0]]);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn excluded_tags_are_not_run() {
+    let stand_in = StandIn::start().await;
+    stand_in.serve("/slides.json", Route::json(&json!([])));
+    stand_in.serve("/exercises.json", Route::json(&json!([])));
+    let dir = temp_dir("template");
+    let mut template = template(
+        &dir,
+        vec![tagged(
+            folder(
+                "Course",
+                vec![
+                    tagged(site(stand_in.url("/slides.json"), "A"), &["slides"]),
+                    site(stand_in.url("/exercises.json"), "B"),
+                ],
+            ),
+            &["hs22"],
+        )],
+    );
+    let dsettings = Arc::new(dsettings(&dir));
+    let indexes = template.select(&"hs22 & !slides".parse::<TagExpr>().unwrap());
+
+    let status = template
+        .prepare_nodes(Arc::clone(&dsettings), &indexes)
+        .await;
+    assert_eq!(status, Status::Success);
+    let report = template.run(dsettings, &indexes).await.unwrap();
+    let sites: Vec<_> = report.sites.iter().map(|site| site.index.clone()).collect();
+    assert_eq!(sites, vec![vec![0, 1]]);
+    assert_eq!(stand_in.requests("/exercises.json").len(), 1);
+    assert!(stand_in.requests("/slides.json").is_empty());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn selecting_a_folder_runs_its_sites() {
+    let stand_in = StandIn::start().await;
+    stand_in.serve("/index.json", Route::json(&json!([])));
+    let dir = temp_dir("template");
+    let index = stand_in.url("/index.json");
+    let mut template = template(
+        &dir,
+        vec![
+            folder(
+                "Course",
+                vec![
+                    site(index.clone(), "A"),
+                    folder("Sub", vec![site(index.clone(), "B")]),
+                ],
+            ),
+            folder("Other", vec![site(index, "C")]),
+        ],
+    );
+    let dsettings = Arc::new(dsettings(&dir));
+    let indexes = HashSet::from([im::vector![0]]);
+
+    let err = template
+        .run(Arc::clone(&dsettings), &indexes)
+        .await
+        .unwrap_err();
+    assert!(matches!(err.kind, TErrorKind::PrepareFailed));
+
+    let status = template
+        .prepare_nodes(Arc::clone(&dsettings), &indexes)
+        .await;
+    assert_eq!(status, Status::Success);
+    assert!(template.is_prepared(&indexes));
+    assert!(template.root.children[1].children[0].path.is_none());
+
+    let report = template.run(dsettings, &indexes).await.unwrap();
+    let mut sites: Vec<_> = report.sites.iter().map(|site| site.index.clone()).collect();
+    sites.sort();
+    assert_eq!(sites, vec![vec![0, 0], vec![0, 1, 0]]);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+// a v0 template, written by hand with comments and without the version header
+const TEMPLATE_V0: &str = r#"
+// no header before version 1
+(
+    children: [
+        (
+            ty: Folder((name: "Course")),
+            children: [
+                (
+                    ty: Site((
+                        module: Listing((url: "http://localhost/index.json", name: "Slides")),
+                        storage: (files: {}, history: []),
+                        download_args: None,
+                    )),
+                    children: [],
+                    cached_path_segment: Some("Slides"),
+                ),
+            ],
+            cached_path_segment: None,
+        ),
+    ],
+)
+"#;
+
+const TEMPLATE_V1: &str = r#"(version:1,root:(children:[(ty:Site((id:"6f1c0d2e-8a4b-4c3d-9e5f-0a1b2c3d4e5f",module:Listing((url:"http://localhost/index.json",name:"Slides")),storage:(files:{},history:[]),download_args:None)),children:[],cached_path_segment:None),(ty:Site((module:Listing((url:"http://localhost/other.json",name:"Other")),storage:(files:{},history:[]),download_args:None)),children:[],cached_path_segment:None)]))"#;
+
+fn site_id(node: &NodeType) -> Uuid {
+    match node {
+        NodeType::Site(site) => site.id,
+        NodeType::Folder(_) => panic!("expected a site"),
+    }
+}
+
+#[tokio::test]
+async fn v0_templates_are_migrated() {
+    let dir = temp_dir("template");
+    let path = dir.join("template.ron");
+    std::fs::write(&path, TEMPLATE_V0).unwrap();
+
+    let (template, _rx) = Template::load(&path).await.unwrap();
+    assert_eq!(
+        std::fs::read_to_string(dir.join("template.ron.v0.bak")).unwrap(),
+        TEMPLATE_V0
+    );
+    let course = &template.root.children[0];
+    assert!(matches!(&course.ty, NodeType::Folder(folder) if folder.name == "Course"));
+    assert_eq!(
+        course.children[0].cached_path_segment,
+        Some(PathBuf::from("Slides"))
+    );
+
+    // the id given by the migration names the state file and survives the save
+    let id = site_id(&course.children[0].ty);
+    template.save().await.unwrap();
+    assert!(state::state_dir(&path).join(format!("{}.ron", id)).exists());
+    let (template, _rx) = Template::load(&path).await.unwrap();
+    assert_eq!(site_id(&template.root.children[0].children[0].ty), id);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn v1_templates_are_migrated() {
+    let dir = temp_dir("template");
+    let path = dir.join("template.ron");
+    std::fs::write(&path, TEMPLATE_V1).unwrap();
+
+    let (template, _rx) = Template::load(&path).await.unwrap();
+    assert_eq!(
+        std::fs::read_to_string(dir.join("template.ron.v1.bak")).unwrap(),
+        TEMPLATE_V1
+    );
+    let children = &template.root.children;
+    assert_eq!(
+        site_id(&children[0].ty),
+        Uuid::parse_str("6f1c0d2e-8a4b-4c3d-9e5f-0a1b2c3d4e5f").unwrap()
+    );
+    assert!(!site_id(&children[1].ty).is_nil());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn current_templates_are_not_backed_up() {
+    let dir = temp_dir("template");
+    let path = dir.join("template.ron");
+    let root = RawRootNode {
+        children: vec![folder("Course", vec![])],
+    };
+    std::fs::write(&path, format::serialize(&root).unwrap()).unwrap();
+
+    let (template, _rx) = Template::load(&path).await.unwrap();
+    assert_eq!(template.root.children.len(), 1);
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+    std::fs::write(&path, "(version:3,root:(children:[]))").unwrap();
+    assert!(Template::load(&path).await.is_err());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn state_files_of_removed_sites_are_deleted() {
+    let dir = temp_dir("template");
+    let (kept, removed) = (Uuid::new_v4(), Uuid::new_v4());
+    let state_dir = state::state_dir(&dir.join("template.ron"));
+
+    template(
+        &dir,
+        vec![
+            with_id(site("/a".to_owned(), "A"), kept),
+            folder("Old", vec![with_id(site("/b".to_owned(), "B"), removed)]),
+        ],
+    )
+    .save()
+    .await
+    .unwrap();
+    assert!(state_dir.join(format!("{}.ron", removed)).exists());
+    std::fs::write(state_dir.join("notes.ron"), "()").unwrap();
+
+    template(&dir, vec![with_id(site("/a".to_owned(), "A"), kept)])
+        .save()
+        .await
+        .unwrap();
+    assert!(state_dir.join(format!("{}.ron", kept)).exists());
+    assert!(!state_dir.join(format!("{}.ron", removed)).exists());
+    // only state files are touched
+    assert!(state_dir.join("notes.ron").exists());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
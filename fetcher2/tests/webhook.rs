@@ -0,0 +1,143 @@
+use std::path::PathBuf;
+
+use chrono::Utc;
+use uuid::Uuid;
+
+use fetcher2::notifier::{render_body, WebhookEvent, WebhookNotifier, WebhookSettings};
+use fetcher2::template::node_type::site::{MsgKind, TaskMsg};
+use fetcher2::template::report::RunReport;
+
+use support::{Route, StandIn};
+
+mod support;
+
+async fn stand_in(routes: Vec<Route>) -> StandIn {
+    let stand_in = StandIn::start().await;
+    stand_in.serve_in_order("/hook", routes);
+    stand_in
+}
+
+fn bodies(stand_in: &StandIn) -> Vec<String> {
+    stand_in
+        .requests("/hook")
+        .into_iter()
+        .map(|request| request.body)
+        .collect()
+}
+
+fn settings(url: String, body_template: Option<&str>) -> WebhookSettings {
+    WebhookSettings {
+        url,
+        body_template: body_template.map(str::to_owned),
+        batch_delay: 1,
+        batch_size: 50,
+        retries: 3,
+    }
+}
+
+fn report() -> RunReport {
+    RunReport {
+        run_id: Uuid::new_v4(),
+        started: Utc::now(),
+        finished: Utc::now(),
+        sites: Vec::new(),
+    }
+}
+
+#[tokio::test]
+async fn batches_events_until_run_finished() {
+    let stand_in = stand_in(vec![Route::file("")]).await;
+    let (notifier, handle) = WebhookNotifier::new(settings(stand_in.url("/hook"), None));
+
+    notifier.notify_msg(&TaskMsg::new(
+        PathBuf::from("/tmp/a.pdf"),
+        PathBuf::from("a.pdf"),
+        MsgKind::AddedFile,
+    ));
+    notifier.notify_msg(&TaskMsg::new(
+        PathBuf::from("/tmp/b.pdf"),
+        PathBuf::from("b.pdf"),
+        MsgKind::ReplacedFile(PathBuf::from("/tmp/b_old.pdf")),
+    ));
+    // unchanged files are not sent
+    notifier.notify_msg(&TaskMsg::new(
+        PathBuf::from("/tmp/c.pdf"),
+        PathBuf::from("c.pdf"),
+        MsgKind::NotModified,
+    ));
+    notifier.run_finished(&report());
+    drop(notifier);
+    handle.await.unwrap();
+
+    let bodies = bodies(&stand_in);
+    assert_eq!(bodies.len(), 1);
+    let body: serde_json::Value = serde_json::from_str(&bodies[0]).unwrap();
+    let kinds: Vec<_> = body["events"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|event| event["kind"].as_str().unwrap().to_owned())
+        .collect();
+    assert_eq!(kinds, ["file_added", "file_replaced", "run_finished"]);
+    assert!(body["text"].as_str().unwrap().contains("New file: a.pdf"));
+}
+
+#[tokio::test]
+async fn retries_failed_requests() {
+    let stand_in = stand_in(vec![Route::error(500), Route::file("")]).await;
+    let (notifier, handle) = WebhookNotifier::new(settings(
+        stand_in.url("/hook"),
+        Some(r#"{"text": "{{text}}"}"#),
+    ));
+
+    notifier.run_finished(&report());
+    drop(notifier);
+    handle.await.unwrap();
+
+    let bodies = bodies(&stand_in);
+    assert_eq!(bodies.len(), 2);
+    assert_eq!(bodies[0], bodies[1]);
+    assert_eq!(
+        bodies[0],
+        r#"{"text": "Run finished: 0 new, 0 updated, 0 errors"}"#
+    );
+}
+
+#[test]
+fn template_escapes_text() {
+    let batch = [
+        WebhookEvent::FileAdded {
+            path: PathBuf::from("a \"quoted\".pdf"),
+            time: None,
+        },
+        WebhookEvent::FileAdded {
+            path: PathBuf::from("b.pdf"),
+            time: None,
+        },
+    ];
+    let body = render_body(
+        Some(r#"{"msgtype": "m.text", "body": "{{text}}", "count": {{count}}}"#),
+        &batch,
+    )
+    .unwrap();
+    let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(body["body"], "New file: a \"quoted\".pdf\nNew file: b.pdf");
+    assert_eq!(body["count"], 2);
+}
+
+#[test]
+fn placeholders_in_the_text_are_kept() {
+    let batch = [WebhookEvent::FileAdded {
+        path: PathBuf::from("{{count}} and {{events}}.pdf"),
+        time: None,
+    }];
+    let body = render_body(
+        Some(r#"{"text": "{{text}}", "count": {{count}}, "other": "{{other}}"}"#),
+        &batch,
+    )
+    .unwrap();
+    let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(body["text"], "New file: {{count}} and {{events}}.pdf");
+    assert_eq!(body["count"], 1);
+    assert_eq!(body["other"], "{{other}}");
+}
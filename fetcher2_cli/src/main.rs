@@ -1,12 +1,19 @@
 use std::path::PathBuf;
-use std::sync::Arc;
+use std::str::FromStr;
 
 use clap::Parser;
-use tokio::sync::mpsc::Receiver;
+use futures::StreamExt;
+use tracing::error;
 
+use fetcher2::event_stream::{EventSink, EventTarget};
+use fetcher2::fetcher::EventStream;
+use fetcher2::logging::{self, LogFile, LogRotation, LogSettings};
+use fetcher2::metrics::MetricsServer;
+use fetcher2::notifier::WebhookNotifier;
+use fetcher2::session::Session;
 use fetcher2::settings::DownloadSettings;
-use fetcher2::template::nodes::node::NodeEvent;
-use fetcher2::template::Template;
+use fetcher2::template::report::RunReport;
+use fetcher2::{Fetcher, Selection};
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
@@ -18,24 +25,172 @@ struct Args {
     /// Path to the template file
     #[clap(short, long)]
     template_path: PathBuf,
+
+    /// Only run the nodes matching this tag expression, e.g. "weekly & !huge"
+    #[clap(long)]
+    tags: Option<String>,
+
+    /// Write a report of the run to this file, as json, md or html depending on the extension
+    #[clap(long)]
+    report: Option<ReportTarget>,
+
+    /// Write every event as a json line to "-" (stdout), "unix:<socket path>" or a file
+    #[clap(long)]
+    events: Option<EventTarget>,
+
+    /// Save every request and response to this folder, without the credentials
+    #[clap(long, conflicts_with = "replay")]
+    record: Option<PathBuf>,
+
+    /// Answer every request from a folder made with --record, nothing is sent
+    #[clap(long)]
+    replay: Option<PathBuf>,
+
+    /// Log filter for stderr like "info" or "warn,fetcher2::session=debug", FETCHER2_LOG takes precedence
+    #[clap(long, default_value = "info")]
+    log: String,
+
+    /// Also write the logs to files in this folder
+    #[clap(long)]
+    log_dir: Option<PathBuf>,
+
+    /// When to start a new log file: hourly, daily or never
+    #[clap(long, default_value = "daily")]
+    log_rotation: LogRotation,
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let args = Args::parse();
+    let _log_guard = logging::init(&LogSettings {
+        filter: args.log.clone(),
+        stderr: true,
+        file: args.log_dir.clone().map(|dir| LogFile {
+            dir,
+            rotation: args.log_rotation,
+        }),
+    })?;
     let settings_bytes = tokio::fs::read(args.settings_path).await?;
-    let settings: Arc<DownloadSettings> = Arc::new(ron::de::from_bytes(&settings_bytes)?);
-    let (template, rx) = Template::load(&args.template_path).await?;
-    let printer = tokio::spawn(event_printer(rx));
-    if let Ok(template) = template.prepare(settings.clone()).await {
-        template.run_root(settings.clone()).await;
-    } else {
-        println!("Could not prepare template")
+    let settings: DownloadSettings = ron::de::from_bytes(&settings_bytes)?;
+    let (notifier, notifier_handle) = match settings.webhook.clone() {
+        Some(webhook) => {
+            let (notifier, handle) = WebhookNotifier::new(webhook);
+            (Some(notifier), Some(handle))
+        }
+        None => (None, None),
+    };
+    // metrics.textfile is written by every run, the server only lives as long as the run
+    let _metrics_server = match settings.metrics.port()? {
+        Some(port) => Some(MetricsServer::start(port).await?),
+        None => None,
+    };
+    let session = match (&args.record, &args.replay) {
+        (Some(dir), _) => Some(Session::recording(&settings, dir)?),
+        (_, Some(dir)) => Some(Session::replaying(&settings, dir)?),
+        _ => None,
+    };
+    let mut builder = Fetcher::builder(settings, &args.template_path);
+    if let Some(session) = session {
+        builder = builder.session(session);
+    }
+    let fetcher = builder.build().await?;
+    let sink = match &args.events {
+        Some(target) => Some(EventSink::open(target).await?),
+        None => None,
+    };
+    let events = fetcher.events().expect("Events are only taken once");
+    let forwarder = tokio::spawn(forward_events(events, sink, notifier.clone()));
+    let selection = match args.tags {
+        Some(tags) => Selection::Tags(tags.parse()?),
+        None => Selection::All,
+    };
+    let report = match fetcher.run(selection).await {
+        Ok(report) => Some(report),
+        Err(err) => {
+            error!(error = %err, "Could not run template");
+            None
+        }
+    };
+    fetcher.save().await?;
+    // the event stream ends once the fetcher is gone
+    drop(fetcher);
+    forwarder.await?;
+
+    if let Some(notifier) = notifier {
+        if let Some(report) = &report {
+            notifier.run_finished(report);
+        }
+        // the remaining batch is sent once every notifier is dropped
+        drop(notifier);
+    }
+    if let Some(handle) = notifier_handle {
+        handle.await?;
+    }
+
+    if let (Some(report), Some(target)) = (report, args.report) {
+        write_report(&report, &target).await?;
     }
-    printer.await?;
     Ok(())
 }
 
-async fn event_printer(_rx: Receiver<NodeEvent>) {
-    todo!()
+#[derive(Debug, Clone, Copy)]
+enum ReportFormat {
+    Json,
+    Markdown,
+    Html,
+}
+
+// the format is checked with the arguments, so a typo doesn't waste a whole run
+#[derive(Debug)]
+struct ReportTarget {
+    path: PathBuf,
+    format: ReportFormat,
+}
+
+impl FromStr for ReportTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let path = PathBuf::from(s);
+        let format = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => ReportFormat::Json,
+            Some("md") => ReportFormat::Markdown,
+            Some("html" | "htm") => ReportFormat::Html,
+            _ => {
+                return Err(format!(
+                    "Unknown report format {:?}, use json, md or html",
+                    path
+                ))
+            }
+        };
+        Ok(Self { path, format })
+    }
+}
+
+async fn write_report(report: &RunReport, target: &ReportTarget) -> anyhow::Result<()> {
+    let content = match target.format {
+        ReportFormat::Json => report.to_json()?,
+        ReportFormat::Markdown => report.to_markdown(),
+        ReportFormat::Html => report.to_html(),
+    };
+    tokio::fs::write(&target.path, content).await?;
+    Ok(())
+}
+
+async fn forward_events(
+    mut events: EventStream,
+    mut sink: Option<EventSink>,
+    notifier: Option<WebhookNotifier>,
+) {
+    while let Some(event) = events.next().await {
+        if let Some(writer) = &mut sink {
+            if let Err(err) = writer.write(&event).await {
+                error!(error = %err, "Could not write event");
+                sink = None;
+            }
+        }
+        if let Some(notifier) = &notifier {
+            notifier.notify_event(&event);
+        }
+    }
 }
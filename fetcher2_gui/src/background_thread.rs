@@ -2,7 +2,7 @@ use std::collections::HashSet;
 use std::future::Future;
 use std::mem;
 use std::path::PathBuf;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use druid::{ExtEventSink, Selector, SingleUse, Target};
 use druid_widget_nursery::selectors;
@@ -12,9 +12,14 @@ use futures::prelude::stream::FuturesUnordered;
 use futures::{FutureExt, StreamExt};
 use tokio::sync::mpsc::Receiver;
 use tokio::task::JoinHandle;
-
-use fetcher2::settings::DownloadSettings;
-use fetcher2::template::nodes::node::NodeEvent;
+use tracing::{debug, info, trace, warn};
+
+use fetcher2::metrics::MetricsServer;
+use fetcher2::notifier::WebhookNotifier;
+use fetcher2::settings::{DownloadSettings, MetricsSettings};
+use fetcher2::template::nodes::node::{NodeEvent, Status};
+use fetcher2::template::report::RunReport;
+use fetcher2::template::tags::TagExpr;
 use fetcher2::template::{Prepared, Template, UnPrepared};
 use fetcher2::TError;
 
@@ -34,15 +39,21 @@ selectors! {
 pub const NODE_EVENT: Selector<SingleUse<NodeEvent>> =
     Selector::new("fetcher2.communucation.node_event");
 
+// Replaced whenever new settings arrive, dropping the old one sends its last batch
+type SharedNotifier = Arc<Mutex<Option<WebhookNotifier>>>;
+
 pub enum ThreadMsg {
     SettingsRequired,
     TemplateLoadingError(TError),
     TemplateSaveError(TError),
+    TagExpressionError(TError),
+    RunError(TError),
 }
 
 enum RunType {
     Root,
     Indexes(HashSet<NodeIndex>),
+    Tags(TagExpr),
 }
 
 enum PostCommand {
@@ -60,6 +71,13 @@ impl TemplateState {
         matches!(self, Self::Prepared(_))
     }
 
+    pub fn select(&self, expr: &TagExpr) -> HashSet<NodeIndex> {
+        match self {
+            Self::Prepared(template) => template.select(expr),
+            Self::UnPrepared(template) => template.select(expr),
+        }
+    }
+
     pub async fn inform_of_cancel(&self) {
         match self {
             Self::Prepared(template) => template.inform_of_cancel().await,
@@ -90,6 +108,22 @@ impl TemplateState {
             true
         }
     }
+
+    pub async fn prepare_nodes(
+        &mut self,
+        dsettings: Arc<DownloadSettings>,
+        indexes: &HashSet<NodeIndex>,
+    ) -> bool {
+        if let TemplateState::UnPrepared(template) = self {
+            if template.prepare_nodes(dsettings, indexes).await == Status::Failure {
+                return false;
+            }
+            if let Ok(prepared_template) = mem::take(template).try_into_prepared() {
+                *self = TemplateState::Prepared(prepared_template);
+            }
+        }
+        true
+    }
 }
 
 struct TemplateData {
@@ -115,28 +149,31 @@ impl TemplateData {
 
     pub fn new(
         template: Template<UnPrepared>,
-        rx: Receiver<NodeEvent>,
         sink: ExtEventSink,
+        notifier: SharedNotifier,
     ) -> Self {
+        let rx = template.subscribe_lossy();
         Self {
             template_state: TemplateState::UnPrepared(template),
-            handle: tokio::spawn(forward_msgs(rx, sink)),
+            handle: tokio::spawn(forward_msgs(rx, sink, notifier)),
         }
     }
 
+    // Progress is dropped while the gui is behind, downloads never wait for it
     pub async fn replace(
         &mut self,
         template: Template<UnPrepared>,
-        tx: Receiver<NodeEvent>,
         sink: ExtEventSink,
+        notifier: SharedNotifier,
     ) {
+        let tx = template.subscribe_lossy();
         self.template_state = TemplateState::UnPrepared(template);
 
         let dummy_handle = tokio::spawn(async {});
         let old_handle = mem::replace(&mut self.handle, dummy_handle);
         // TODO not unwrap
         old_handle.await.unwrap();
-        let new_handle = tokio::spawn(forward_msgs(tx, sink));
+        let new_handle = tokio::spawn(forward_msgs(tx, sink, notifier));
         self.handle = new_handle;
     }
 }
@@ -154,6 +191,8 @@ async fn manager(gui_rx: flume::Receiver<Msg>, sink: ExtEventSink) {
     let template_data = TemplateData::empty();
     let template_data = tokio::sync::RwLock::new(template_data);
     let mut dsettings: Option<Arc<DownloadSettings>> = None;
+    let notifier = SharedNotifier::default();
+    let mut metrics_server: Option<MetricsServer> = None;
 
     let mut futs = FuturesUnordered::new();
     let mut abort_handles = Vec::new();
@@ -161,11 +200,11 @@ async fn manager(gui_rx: flume::Receiver<Msg>, sink: ExtEventSink) {
     loop {
         tokio::select! {
             Ok(msg) = gui_rx.recv_async() => {
-                dbg!("{:?}", &msg);
+                debug!(?msg, "Message from the gui");
                 match msg {
                     Msg::StartAll => {
                         with_settings(
-                            |settings| run_template(&template_data, settings, RunType::Root),
+                            |settings| run_template(&template_data, &notifier, sink.clone(), settings, RunType::Root),
                             dsettings.clone(),
                             &mut futs,
                             &mut abort_handles,
@@ -174,19 +213,43 @@ async fn manager(gui_rx: flume::Receiver<Msg>, sink: ExtEventSink) {
                     },
                     Msg::StartByIndex(indexes) => {
                         with_settings(
-                            |settings| run_template(&template_data, settings, RunType::Indexes(indexes)),
+                            |settings| run_template(&template_data, &notifier, sink.clone(), settings, RunType::Indexes(indexes)),
                             dsettings.clone(),
                             &mut futs,
                             &mut abort_handles,
                             sink.clone()
                         );
                     },
+                    Msg::StartByTags(tags) => match tags.parse::<TagExpr>() {
+                        Ok(expr) => {
+                            with_settings(
+                                |settings| run_template(&template_data, &notifier, sink.clone(), settings, RunType::Tags(expr)),
+                                dsettings.clone(),
+                                &mut futs,
+                                &mut abort_handles,
+                                sink.clone()
+                            );
+                        }
+                        Err(err) => {
+                            sink.submit_command(
+                                MSG_FROM_THREAD,
+                                SingleUse::new(ThreadMsg::TagExpressionError(err)),
+                                Target::Global,
+                            )
+                            .unwrap()
+                        }
+                    },
                     Msg::Cancel => {
                         cancel_all(&mut abort_handles);
                         let fut = async { template_data.read().await.template_state.inform_of_cancel().await; PostCommand::None };
                         add_new_future(fut, &mut futs, &mut abort_handles);
                     },
                     Msg::NewSettings(new_settings) => {
+                        *notifier.lock().unwrap() = new_settings
+                            .webhook
+                            .clone()
+                            .map(|webhook| WebhookNotifier::new(webhook).0);
+                        update_metrics_server(&mut metrics_server, &new_settings.metrics).await;
                         dsettings = Some(Arc::new(new_settings));
                         with_settings(
                             |settings| prepare_template(&template_data, settings),
@@ -196,14 +259,14 @@ async fn manager(gui_rx: flume::Receiver<Msg>, sink: ExtEventSink) {
                             sink.clone()
                         );
                     },
-                    Msg::NewTemplate((new_template, new_rx)) => {
+                    Msg::NewTemplate((new_template, _)) => {
                         cancel_all(&mut abort_handles);
-                        let fut = replace_template(&template_data, new_template, new_rx, sink.clone());
+                        let fut = replace_template(&template_data, new_template, sink.clone(), notifier.clone());
                         add_new_future(fut, &mut futs, &mut abort_handles);
                     },
                     Msg::NewTemplateByPath(path) => {
                         cancel_all(&mut abort_handles);
-                        let fut = replace_template_by_path(&template_data, path, sink.clone());
+                        let fut = replace_template_by_path(&template_data, path, sink.clone(), notifier.clone());
                         add_new_future(fut, &mut futs, &mut abort_handles);
                     },
                     Msg::ExitAndSave => {
@@ -225,7 +288,7 @@ async fn manager(gui_rx: flume::Receiver<Msg>, sink: ExtEventSink) {
                 }
             },
             else => {
-                dbg!("BREAK LOOP");
+                debug!("Gui channel closed");
                 break
             },
         }
@@ -235,12 +298,37 @@ async fn manager(gui_rx: flume::Receiver<Msg>, sink: ExtEventSink) {
     // We use write so we are sure the other operations are finished
     template_data.write().await.save().await.unwrap();
 
-    println!("GRACEFUL EXIT");
+    info!("Background thread exited");
+}
+
+// restarts the server only if the port changed
+async fn update_metrics_server(server: &mut Option<MetricsServer>, settings: &MetricsSettings) {
+    let port = match settings.port() {
+        Ok(port) => port,
+        Err(err) => {
+            warn!(error = %err, "Could not start the metrics server");
+            return;
+        }
+    };
+    if port.is_some() && port == server.as_ref().map(|server| server.addr().port()) {
+        return;
+    }
+    // the old port has to be free before it can be bound again
+    *server = None;
+    if let Some(port) = port {
+        match MetricsServer::start(port).await {
+            Ok(new_server) => *server = Some(new_server),
+            Err(err) => warn!(error = %err, port, "Could not start the metrics server"),
+        }
+    }
 }
 
-async fn forward_msgs(mut rx: Receiver<NodeEvent>, sink: ExtEventSink) {
+async fn forward_msgs(mut rx: Receiver<NodeEvent>, sink: ExtEventSink, notifier: SharedNotifier) {
     while let Some(event) = rx.recv().await {
-        dbg!(&event);
+        trace!(?event, "Node event");
+        if let Some(notifier) = notifier.lock().unwrap().as_ref() {
+            notifier.notify_event(&event);
+        }
 
         sink.submit_command(NODE_EVENT, SingleUse::new(event), Target::Global)
             .expect("Main Thread existed before this one");
@@ -310,11 +398,12 @@ async fn replace_template_by_path(
     old_template_data: &tokio::sync::RwLock<TemplateData>,
     path: PathBuf,
     sink: ExtEventSink,
+    notifier: SharedNotifier,
 ) -> PostCommand {
-    dbg!("starting load");
+    debug!(?path, "Loading template");
     match Template::load(path.as_path()).await {
-        Ok((new_template, new_rx)) => {
-            replace_template(old_template_data, new_template, new_rx, sink).await
+        Ok((new_template, _)) => {
+            replace_template(old_template_data, new_template, sink, notifier).await
         }
         Err(err) => {
             sink.submit_command(
@@ -331,10 +420,10 @@ async fn replace_template_by_path(
 async fn replace_template(
     old_template_data: &tokio::sync::RwLock<TemplateData>,
     new_template: Template<UnPrepared>,
-    new_rx: Receiver<NodeEvent>,
     sink: ExtEventSink,
+    notifier: SharedNotifier,
 ) -> PostCommand {
-    dbg!("replace load");
+    debug!("Replacing template");
     old_template_data.read().await.inform_of_cancel().await;
     let mut wl = old_template_data.write().await;
     sink.submit_command(
@@ -371,42 +460,84 @@ async fn replace_template(
         )
         .unwrap()
     }
-    wl.replace(new_template, new_rx, sink).await;
-    dbg!("finished");
+    wl.replace(new_template, sink, notifier).await;
+    debug!("Replaced template");
     PostCommand::RunPrepare
 }
 
 async fn run_template(
     template_data: &tokio::sync::RwLock<TemplateData>,
+    notifier: &SharedNotifier,
+    sink: ExtEventSink,
     dsettings: Arc<DownloadSettings>,
     ty: RunType,
 ) -> PostCommand {
+    let ty = match ty {
+        RunType::Tags(expr) => {
+            RunType::Indexes(template_data.read().await.template_state.select(&expr))
+        }
+        ty => ty,
+    };
     loop {
         let rl = template_data.read().await;
-        if let TemplateState::Prepared(template) = &rl.template_state {
-            match ty {
-                RunType::Root => template.run_root(dsettings.clone()).await,
-                RunType::Indexes(ref indexes) => template.run(dsettings.clone(), indexes).await,
-            };
-            return PostCommand::None;
+        match (&rl.template_state, &ty) {
+            (TemplateState::Prepared(template), RunType::Root) => {
+                let report = template.run_root(dsettings.clone()).await;
+                finish_run(notifier, &sink, report);
+                return PostCommand::None;
+            }
+            (TemplateState::Prepared(template), RunType::Indexes(indexes)) => {
+                let report = template.run(dsettings.clone(), indexes).await;
+                finish_run(notifier, &sink, report);
+                return PostCommand::None;
+            }
+            (TemplateState::UnPrepared(template), RunType::Indexes(indexes))
+                if template.is_prepared(indexes) =>
+            {
+                let report = template.run(dsettings.clone(), indexes).await;
+                finish_run(notifier, &sink, report);
+                return PostCommand::None;
+            }
+            _ => (),
         }
         drop(rl);
-        if !template_data
-            .write()
-            .await
-            .template_state
-            .prepare(dsettings.clone())
-            .await
-        {
+        let mut wl = template_data.write().await;
+        let success = match &ty {
+            RunType::Root | RunType::Tags(_) => wl.template_state.prepare(dsettings.clone()).await,
+            RunType::Indexes(indexes) => {
+                wl.template_state
+                    .prepare_nodes(dsettings.clone(), indexes)
+                    .await
+            }
+        };
+        if !success {
             return PostCommand::None;
         }
     }
 }
+
+fn finish_run(notifier: &SharedNotifier, sink: &ExtEventSink, report: Result<RunReport, TError>) {
+    match report {
+        Ok(report) => {
+            if let Some(notifier) = notifier.lock().unwrap().as_ref() {
+                notifier.run_finished(&report);
+            }
+        }
+        Err(err) => sink
+            .submit_command(
+                MSG_FROM_THREAD,
+                SingleUse::new(ThreadMsg::RunError(err)),
+                Target::Global,
+            )
+            .unwrap(),
+    }
+}
+
 async fn prepare_template(
     template_data: &tokio::sync::RwLock<TemplateData>,
     dsettings: Arc<DownloadSettings>,
 ) -> PostCommand {
-    dbg!("start prepare");
+    debug!("Preparing template");
     template_data
         .write()
         .await
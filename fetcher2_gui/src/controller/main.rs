@@ -31,6 +31,7 @@ selectors! {
 pub enum Msg {
     StartAll,
     StartByIndex(HashSet<NodeIndex>),
+    StartByTags(String),
     Cancel,
     NewSettings(DownloadSettings),
     NewTemplate((Template<UnPrepared>, Receiver<NodeEvent>)),
@@ -59,6 +60,10 @@ impl MainController {
             ThreadMsg::TemplateSaveError(err) => {
                 show_err(ctx, data, env, err, "Could not save template")
             }
+            ThreadMsg::TagExpressionError(err) => {
+                show_err(ctx, data, env, err, "Could not parse tag expression")
+            }
+            ThreadMsg::RunError(err) => show_err(ctx, data, env, err, "Could not start run"),
         };
     }
 
@@ -7,6 +7,7 @@ use druid::{
 use druid_widget_nursery::selectors;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
+use tracing::{debug, warn};
 
 use config::ctypes::CType;
 use config::deserializer::ConfigDeserializer;
@@ -56,7 +57,7 @@ impl<T: Travel + Data + DeserializeOwned + Serialize> Widget<Option<T>> for CTyp
                     *data = Some(new_data);
                     ctx.submit_command(CLOSE_WINDOW);
                 } else {
-                    dbg!("INVALID DATA FOUND");
+                    warn!("Invalid data found");
                 }
             }
             _ => (),
@@ -65,7 +66,7 @@ impl<T: Travel + Data + DeserializeOwned + Serialize> Widget<Option<T>> for CTyp
         let old_data = self.ty.clone();
         self.child.event(ctx, event, &mut self.ty, env);
         if !old_data.same(&self.ty) {
-            dbg!("DATA CHANGED");
+            debug!("Data changed");
             ctx.request_update()
         }
     }
@@ -41,6 +41,9 @@ pub struct AppData {
 
     #[data(ignore)]
     pub folder_header_sizes: Vec<f64>,
+
+    #[serde(default)]
+    pub tag_filter: String,
 }
 
 impl AppData {
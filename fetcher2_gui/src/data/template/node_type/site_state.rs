@@ -3,6 +3,8 @@ use std::sync::Arc;
 use druid::im::Vector;
 use druid::Data;
 
+use fetcher2::template::node_type::extract::ExtractEventKind;
+use fetcher2::template::node_type::hook::HookEventKind;
 use fetcher2::template::node_type::site::{
     DownloadEventKind, LoginEventKind, MsgKind, RunEventKind, SiteEventKind, TaskMsg,
     UrlFetchEventKind,
@@ -17,6 +19,9 @@ pub struct SiteState {
     pub login: LoginState,
     pub fetch: FetchState,
     pub download: DownloadState,
+    pub extract: ExtractState,
+    pub hook: HookState,
+    pub warnings: Vector<String>,
 }
 
 impl Default for SiteState {
@@ -32,6 +37,9 @@ impl SiteState {
             login: LoginState::new(),
             fetch: FetchState::new(),
             download: DownloadState::new(),
+            extract: ExtractState::new(),
+            hook: HookState::new(),
+            warnings: Vector::new(),
         }
     }
 
@@ -40,6 +48,9 @@ impl SiteState {
         self.login.reset();
         self.fetch.reset();
         self.download.reset();
+        self.extract.reset();
+        self.hook.reset();
+        self.warnings.clear();
     }
 
     pub fn update(&mut self, event: SiteEventKind, history: &mut Vector<TaskMsg>) {
@@ -51,6 +62,10 @@ impl SiteState {
             SiteEventKind::Login(login_event) => self.login.update(login_event),
             SiteEventKind::UrlFetch(fetch_event) => self.fetch.update(fetch_event),
             SiteEventKind::Download(down_event) => self.download.update(down_event, history),
+            SiteEventKind::Extract(extract_event) => self.extract.update(extract_event),
+            SiteEventKind::Hook(hook_event) => self.hook.update(hook_event),
+            SiteEventKind::Warning(warning) => self.warnings.push_back(warning),
+            SiteEventKind::RateLimitWait(_) => (),
         }
     }
 
@@ -87,7 +102,7 @@ impl LoginState {
             LoginEventKind::Start => self.count += 1,
             LoginEventKind::Finish => self.count -= 1,
             LoginEventKind::Err(err) => {
-                self.errs.push_back(Arc::new(err));
+                self.errs.push_back(err);
                 self.count -= 1
             }
         }
@@ -128,7 +143,7 @@ impl FetchState {
             UrlFetchEventKind::Start => self.count += 1,
             UrlFetchEventKind::Finish => self.count -= 1,
             UrlFetchEventKind::Err(err) => {
-                self.errs.push_back(Arc::new(err));
+                self.errs.push_back(err);
                 self.count -= 1
             }
         }
@@ -145,6 +160,88 @@ impl FetchState {
     }
 }
 
+#[derive(Debug, Clone, Data)]
+pub struct ExtractState {
+    pub count: usize,
+    pub errs: Vector<Arc<TError>>,
+}
+
+impl ExtractState {
+    pub fn new() -> Self {
+        Self {
+            count: 0,
+            errs: Vector::new(),
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.count = 0;
+        self.errs.clear();
+    }
+
+    pub fn update(&mut self, event: ExtractEventKind) {
+        match event {
+            ExtractEventKind::Start => self.count += 1,
+            ExtractEventKind::Finish(_) => self.count -= 1,
+            ExtractEventKind::Err(err) => {
+                self.errs.push_back(err);
+                self.count -= 1
+            }
+        }
+    }
+
+    pub fn current_state(&self) -> CurrentState {
+        if self.count != 0 {
+            CurrentState::Active("Extracting Archives".into())
+        } else if !self.errs.is_empty() {
+            CurrentState::Error("Error while extracting archives".into())
+        } else {
+            CurrentState::Idle
+        }
+    }
+}
+
+#[derive(Debug, Clone, Data)]
+pub struct HookState {
+    pub count: usize,
+    pub errs: Vector<Arc<TError>>,
+}
+
+impl HookState {
+    pub fn new() -> Self {
+        Self {
+            count: 0,
+            errs: Vector::new(),
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.count = 0;
+        self.errs.clear();
+    }
+
+    pub fn update(&mut self, event: HookEventKind) {
+        match event {
+            HookEventKind::Start => self.count += 1,
+            HookEventKind::Finish(_) => self.count -= 1,
+            HookEventKind::Err(err) => {
+                self.errs.push_back(err);
+                self.count -= 1
+            }
+        }
+    }
+
+    pub fn current_state(&self) -> CurrentState {
+        if self.count != 0 {
+            CurrentState::Active("Running Hooks".into())
+        } else if !self.errs.is_empty() {
+            CurrentState::Error("Error while running hooks".into())
+        } else {
+            CurrentState::Idle
+        }
+    }
+}
+
 #[derive(Debug, Clone, Data)]
 pub struct DownloadState {
     pub count: usize,
@@ -177,6 +274,7 @@ impl DownloadState {
                 self.count += 1;
                 self.total += 1
             }
+            DownloadEventKind::Progress(_) => (),
             DownloadEventKind::Finish(msg) => {
                 match &msg {
                     TaskMsg {
@@ -193,7 +291,7 @@ impl DownloadState {
                 self.count -= 1;
             }
             DownloadEventKind::Err(err) => {
-                self.errs.push_back(Arc::new(err));
+                self.errs.push_back(err);
                 self.count -= 1
             }
         }
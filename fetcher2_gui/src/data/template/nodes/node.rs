@@ -4,6 +4,7 @@ use std::sync::Arc;
 
 use druid::im::Vector;
 use druid::{Data, Lens};
+use tracing::warn;
 
 use fetcher2::template::nodes::node::{Node, NodeEventKind, PathEventKind};
 use fetcher2::TError;
@@ -23,6 +24,8 @@ pub struct NodeData {
     #[data(same_fn = "PartialEq::eq")]
     pub path: Option<PathBuf>,
 
+    pub enabled: bool,
+
     pub state: NodeState,
 }
 
@@ -36,6 +39,7 @@ impl NodeData {
             ty: NodeTypeData::new(&node.ty),
             state: NodeState::new(),
             path: None,
+            enabled: node.enabled,
         }
     }
     pub fn child_indexes(
@@ -87,6 +91,9 @@ impl NodeData {
     }
 
     pub fn state_string(&self) -> String {
+        if !self.enabled {
+            return "Disabled".to_string();
+        }
         match &self.ty {
             NodeTypeData::Folder(_) => "".to_string(),
             NodeTypeData::Site(site) => {
@@ -95,6 +102,8 @@ impl NodeData {
                     site.state.login.current_state(),
                     site.state.fetch.current_state(),
                     site.state.download.current_state(),
+                    site.state.extract.current_state(),
+                    site.state.hook.current_state(),
                     site.state.run_state(),
                 ];
 
@@ -113,7 +122,10 @@ impl NodeData {
                         return msg.to_string();
                     }
                 }
-                "Idle".to_string()
+                match site.state.warnings.last() {
+                    Some(warning) => format!("Idle ({})", warning),
+                    None => "Idle".to_string(),
+                }
             }
         }
     }
@@ -140,6 +152,11 @@ impl NodeData {
                     self.state.canceled = true;
                 }
             }
+            NodeEventKind::Disabled => {
+                self.state.canceled = false;
+                self.state.reset();
+                self.ty.reset_state();
+            }
         }
     }
 }
@@ -209,8 +226,8 @@ impl PathState {
             }
             PathEventKind::Err(err) => {
                 self.count -= 1;
-                dbg!(&err);
-                self.errs.push_back(Arc::new(err));
+                warn!(error = %err, "Could not get the path");
+                self.errs.push_back(err);
             }
             PathEventKind::Cached(new_path) => {
                 *path = Some(new_path);
@@ -2,21 +2,29 @@ use druid::Data;
 use serde::{Deserialize, Serialize};
 
 use config::traveller::Travel;
+use fetcher2::settings::SettingsOverrides;
 use fetcher2::template::node_type::Folder;
 
 #[derive(Clone, Data, Debug, Serialize, Deserialize, Travel)]
 pub struct FolderEditData {
     name: String,
+
+    #[travel(name = "Overrides")]
+    overrides: SettingsOverrides,
 }
 
 impl FolderEditData {
     pub fn new(folder: &Folder) -> Self {
         Self {
             name: folder.name.clone(),
+            overrides: folder.overrides.clone(),
         }
     }
     pub fn raw(self) -> Folder {
-        Folder { name: self.name }
+        Folder {
+            name: self.name,
+            overrides: self.overrides,
+        }
     }
     pub fn name(&self) -> String {
         self.name.clone()
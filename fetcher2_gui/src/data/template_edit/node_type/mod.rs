@@ -1,10 +1,13 @@
+use std::path::PathBuf;
 use std::sync::Arc;
 
+use chrono::{DateTime, Utc};
 use druid::Data;
 use serde::{Deserialize, Serialize};
 
 use config::traveller::Travel;
 use fetcher2::template::node_type::NodeType;
+use fetcher2::template::nodes::node::{Node, PathRefresh};
 
 use crate::data::template_edit::node_type::folder::FolderEditData;
 use crate::data::template_edit::node_type::site_edit::SiteEditData;
@@ -15,17 +18,55 @@ pub mod site_edit;
 #[derive(Debug, Clone, Data, Serialize, Deserialize, Travel)]
 pub struct NodeTypeEditData {
     pub kind: NodeTypeEditKindData,
+
+    #[travel(default = true, name = "Enabled")]
+    pub enabled: bool,
+
+    #[travel(name = "Tags")]
+    pub tags: im::HashSet<String>,
+
+    #[travel(default = PathRefresh::Never, name = "Refresh Folder Name")]
+    pub path_refresh: PathRefresh,
+
+    #[serde(skip)]
+    #[travel(skip)]
+    #[data(same_fn = "PartialEq::eq")]
+    pub cached_path_segment: Option<PathBuf>,
+
+    #[serde(skip)]
+    #[travel(skip)]
+    #[data(same_fn = "PartialEq::eq")]
+    pub cached_at: Option<DateTime<Utc>>,
 }
 
 impl NodeTypeEditData {
-    pub fn new(ty: &NodeType) -> Self {
-        let kind = match ty {
+    pub fn new(node: &Node) -> Self {
+        let kind = match &node.ty {
             NodeType::Site(site) => NodeTypeEditKindData::Site(SiteEditData::new(&(*site))),
             NodeType::Folder(folder) => NodeTypeEditKindData::Folder(FolderEditData::new(folder)),
         };
-        NodeTypeEditData { kind }
+        NodeTypeEditData {
+            kind,
+            enabled: node.enabled,
+            tags: node.tags.clone(),
+            path_refresh: node.path_refresh.clone(),
+            cached_path_segment: node.cached_path_segment.clone(),
+            cached_at: node.cached_at,
+        }
     }
+
+    pub fn restore_cache(&mut self, old: &NodeTypeEditData) {
+        self.cached_path_segment = old.cached_path_segment.clone();
+        self.cached_at = old.cached_at;
+        self.kind.restore_cache(&old.kind);
+    }
+
+    // A renamed folder keeps its cached segment, so the folder on disk gets moved
     pub fn invalidate_cache(&mut self) {
+        if let NodeTypeEditKindData::Site(_) = self.kind {
+            self.cached_path_segment = None;
+            self.cached_at = None;
+        }
         self.kind.invalidate_cache()
     }
 }
@@ -50,6 +91,12 @@ impl NodeTypeEditKindData {
         }
     }
 
+    pub fn restore_cache(&mut self, old: &NodeTypeEditKindData) {
+        if let (Self::Site(site_data), Self::Site(old_site_data)) = (self, old) {
+            site_data.restore_cache(old_site_data)
+        }
+    }
+
     pub fn invalidate_cache(&mut self) {
         match self {
             Self::Site(site_data) => site_data.invalidate_cache(),
@@ -1,7 +1,9 @@
+use std::collections::HashMap;
 use std::sync::Arc;
 
 use druid::Data;
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
 use config::traveller::Travel;
 use fetcher2::site_modules::Module;
@@ -14,9 +16,27 @@ pub struct SiteEditData {
 
     pub download_args: Option<DownloadArgs>,
 
+    /// Accepts invalid and self-signed certificates for this site.
+    /// Prefer adding the root certificate in the settings.
+    #[serde(default)]
+    #[travel(default = false, name = "Insecure TLS")]
+    pub insecure: bool,
+
+    /// Sent with every request of this site, replaces headers with the same name from the settings
+    #[serde(default)]
+    #[travel(name = "Headers")]
+    #[data(same_fn = "PartialEq::eq")]
+    pub headers: HashMap<String, String>,
+
     #[data(ignore)]
+    #[serde(skip)]
     #[travel(skip)]
     pub storage: Option<Arc<SiteStorage>>,
+
+    #[data(ignore)]
+    #[serde(skip)]
+    #[travel(skip)]
+    pub id: Option<Uuid>,
 }
 
 impl SiteEditData {
@@ -24,21 +44,33 @@ impl SiteEditData {
         Self {
             module: site.module.clone(),
             download_args: site.download_args.clone(),
+            insecure: site.insecure,
+            headers: site.headers.clone(),
             storage: Some(site.storage.clone()),
+            id: Some(site.id),
         }
     }
     pub fn raw(self) -> Site {
         Site {
+            id: self.id.unwrap_or_else(Uuid::new_v4),
             module: self.module,
             storage: self.storage.unwrap_or_else(|| Arc::new(SiteStorage::new())),
             download_args: self.download_args,
+            insecure: self.insecure,
+            headers: self.headers,
         }
     }
     pub fn name(&self) -> String {
         self.module.name()
     }
 
+    pub fn restore_cache(&mut self, old: &SiteEditData) {
+        self.storage = old.storage.clone();
+        self.id = old.id;
+    }
+
     pub fn invalidate_cache(&mut self) {
-        self.storage = None
+        self.storage = None;
+        self.id = None;
     }
 }
@@ -19,7 +19,7 @@ impl NodeEditData {
         NodeEditData {
             expanded: true,
             children,
-            ty: Some(NodeTypeEditData::new(&node.ty)),
+            ty: Some(NodeTypeEditData::new(node)),
         }
     }
     pub fn empty(expanded: bool) -> Self {
@@ -40,7 +40,11 @@ impl NodeEditData {
             Some(RawNode {
                 ty: ty.kind.raw(),
                 children,
-                cached_path_segment: None,
+                cached_path_segment: ty.cached_path_segment,
+                cached_at: ty.cached_at,
+                path_refresh: ty.path_refresh,
+                enabled: ty.enabled,
+                tags: ty.tags,
             })
         } else {
             None
@@ -48,12 +52,22 @@ impl NodeEditData {
     }
     pub fn name(&self) -> String {
         if let Some(ty) = &self.ty {
-            ty.kind.name()
+            if ty.enabled {
+                ty.kind.name()
+            } else {
+                format!("{} (Disabled)", ty.kind.name())
+            }
         } else {
             "New Node".to_string()
         }
     }
 
+    pub fn toggle_enabled(&mut self) {
+        if let Some(ty) = &mut self.ty {
+            ty.enabled = !ty.enabled
+        }
+    }
+
     pub fn remove(&mut self, idx: &[usize]) -> NodeEditData {
         match idx.len() {
             0 => unreachable!(),
@@ -10,6 +10,7 @@ use druid::LensExt;
 use druid::{commands, Command, FileDialogOptions, Menu, MenuItem, SingleUse};
 use druid::{Lens, Point, Target, Widget, WidgetExt, WidgetPod, WindowConfig, WindowLevel};
 use druid_widget_nursery::selectors;
+use tracing::debug;
 
 use fetcher2::template::Template;
 
@@ -32,6 +33,7 @@ selectors! {
 selectors! {
     OPEN_NODE: NodeIndex,
     DELETE_NODE: NodeIndex,
+    TOGGLE_NODE: NodeIndex,
     ADD_NODE: (NodeIndex, NodePosition),
 }
 
@@ -105,7 +107,7 @@ impl Widget<EditWindowData> for DataBuffer {
         self.child
             .event(ctx, event, self.data.as_mut().unwrap(), env);
         if !old_data.same(&self.data) {
-            dbg!("CHANGEF");
+            debug!("Template changed");
             let old_root = data.edit_template.root.clone();
             *data = self.data.clone().unwrap();
             data.edit_template.root = old_root;
@@ -198,6 +200,14 @@ where
                 ctx.request_paint();
                 return;
             }
+            Event::Command(cmd) if cmd.is(TOGGLE_NODE) => {
+                ctx.set_handled();
+                let idx = cmd.get_unchecked(TOGGLE_NODE);
+                data.node_mut(idx, |node, _| node.toggle_enabled());
+                ctx.request_update();
+                ctx.request_paint();
+                return;
+            }
             Event::Command(cmd) if cmd.is(ADD_NODE) => {
                 ctx.set_handled();
                 let (idx, pos) = cmd.get_unchecked(ADD_NODE);
@@ -256,7 +266,8 @@ fn make_node_menu(idx: NodeIndex) -> Menu<AppData> {
     let idx2 = idx.clone();
     let idx3 = idx.clone();
     let idx4 = idx.clone();
-    let idx5 = idx;
+    let idx5 = idx.clone();
+    let idx6 = idx;
     Menu::empty()
         .entry(
             MenuItem::new("Edit").on_activate(move |ctx, _data: &mut AppData, _env| {
@@ -268,6 +279,11 @@ fn make_node_menu(idx: NodeIndex) -> Menu<AppData> {
                 ctx.submit_command(DELETE_NODE.with(idx2.clone()))
             }),
         )
+        .entry(MenuItem::new("Enable/Disable").on_activate(
+            move |ctx, _data: &mut AppData, _env| {
+                ctx.submit_command(TOGGLE_NODE.with(idx6.clone()))
+            },
+        ))
         .separator()
         .entry(MenuItem::new("Add new node above").on_activate(
             move |ctx, _data: &mut AppData, _env| {
@@ -326,7 +342,7 @@ where
     ) {
         if let Event::WindowCloseRequested = event {
             data.header_sizes = child.wrapped().wrapped().get_sizes().to_vec().into();
-            dbg!(&data.header_sizes);
+            debug!(header_sizes = ?data.header_sizes, "Saved header sizes");
         }
         child.event(ctx, event, data, env)
     }
@@ -339,7 +355,7 @@ where
         env: &Env,
     ) {
         if let LifeCycle::WidgetAdded = event {
-            dbg!(&data.header_sizes);
+            debug!(header_sizes = ?data.header_sizes, "Restoring header sizes");
             if let Ok(sizes) = data
                 .header_sizes
                 .clone()
@@ -392,7 +408,8 @@ fn node_window(idx: &NodeIndex) -> impl Widget<EditWindowData> {
         Some(Box::new(
             |_ctx, old_data, data: &mut NodeTypeEditData, _| {
                 if let Some(old) = old_data {
-                    if !old.same(data) {
+                    data.restore_cache(old);
+                    if !old.kind.same(&data.kind) {
                         data.invalidate_cache();
                     }
                 }
@@ -10,7 +10,9 @@ use std::thread;
 
 use druid::{AppLauncher, LocalizedString, WidgetExt, WindowDesc};
 use lazy_static::lazy_static;
+use tracing::warn;
 
+use fetcher2::logging::{self, LogFile, LogRotation, LogSettings};
 use fetcher2::{Result, TError};
 
 use crate::background_thread::background_main;
@@ -64,6 +66,32 @@ fn build_window(
 }
 
 pub fn main() {
+    let log_settings = LogSettings {
+        filter: "info,fetcher2_gui=debug,druid=debug,druid_widget_nursery=debug".to_owned(),
+        stderr: true,
+        file: LogFile::default_dir().map(|dir| LogFile {
+            dir,
+            rotation: LogRotation::Daily,
+        }),
+    };
+    // e.g. the log folder can't be created, the app works without the files
+    let _log_guard = match logging::init(&log_settings) {
+        Ok(guard) => Some(guard),
+        Err(err) => match logging::init(&LogSettings {
+            file: None,
+            ..log_settings
+        }) {
+            Ok(guard) => {
+                warn!(error = %err, "Could not log to files, only logging to stderr");
+                Some(guard)
+            }
+            Err(_) => {
+                eprintln!("Could not set up logging: {}", err);
+                None
+            }
+        },
+    };
+
     // let update_thread = thread::spawn(|| {
     //     let status = self_update::backends::github::Update::configure()
     //         .repo_owner("GeorgOhneH")
@@ -95,22 +123,6 @@ pub fn main() {
     let sink = app_launcher.get_external_handle();
     s.send(sink).unwrap();
 
-    use tracing_subscriber::prelude::*;
-    let filter_layer = tracing_subscriber::filter::LevelFilter::TRACE;
-    let filter = tracing_subscriber::filter::EnvFilter::default()
-        .add_directive("my_crate=trace".parse().unwrap())
-        .add_directive("druid=debug".parse().unwrap())
-        .add_directive("druid_widget_nursery=debug".parse().unwrap());
-    let fmt_layer = tracing_subscriber::fmt::layer()
-        // Display target (eg "my_crate::some_mod::submod") with logs
-        .with_target(true);
-
-    tracing_subscriber::registry()
-        .with(filter_layer)
-        .with(fmt_layer)
-        .with(filter)
-        .init();
-
     app_launcher
         // .log_to_console()
         .launch(app_data)
@@ -1,4 +1,6 @@
-use druid::widget::{Button, Controller, CrossAxisAlignment, Flex, Label, SizedBox, WidgetWrapper};
+use druid::widget::{
+    Button, Controller, CrossAxisAlignment, Flex, Label, SizedBox, TextBox, WidgetWrapper,
+};
 use druid::{
     commands, menu, Color, Command, Env, Event, EventCtx, FileInfo, LensExt, Menu, MenuItem,
     SingleUse, SysMods, Target, Widget, WidgetExt, WindowId,
@@ -245,6 +247,16 @@ fn tool_bar() -> impl Widget<AppData> {
             Target::Window(ctx.window_id()),
         ))
     });
+    let tag_filter = TextBox::new()
+        .with_placeholder("weekly & !huge")
+        .lens(AppData::tag_filter);
+    let start_tagged = Button::new("Run Tagged").on_click(|ctx, data: &mut AppData, _| {
+        ctx.submit_command(Command::new(
+            MSG_THREAD,
+            SingleUse::new(Msg::StartByTags(data.tag_filter.clone())),
+            Target::Window(ctx.window_id()),
+        ))
+    });
     let edit = Button::new("Edit").on_click(|ctx, _, _| ctx.submit_command(OPEN_EDIT));
     let settings = Button::new("Settings")
         .on_click(|ctx, _, _env| ctx.submit_command(commands::SHOW_PREFERENCES));
@@ -255,6 +267,10 @@ fn tool_bar() -> impl Widget<AppData> {
         .with_default_spacer()
         .with_child(stop)
         .with_default_spacer()
+        .with_child(tag_filter)
+        .with_default_spacer()
+        .with_child(start_tagged)
+        .with_default_spacer()
         .with_child(edit)
         .with_default_spacer()
         .with_child(settings)
@@ -22,9 +22,7 @@ pub fn show_err(ctx: &mut EventCtx, data: &AppData, env: &Env, err: TError, titl
 fn err_widget(err: TError, title: &str) -> impl Widget<AppData> {
     Flex::column()
         .with_child(Label::new(title))
-        .with_child(
-            Label::new(format!("{:?}", err.kind)).with_line_break_mode(LineBreaking::WordWrap),
-        )
+        .with_child(Label::new(err.to_string()).with_line_break_mode(LineBreaking::WordWrap))
         .with_flex_child(
             Label::new(format!("{}", err.backtrace))
                 .with_line_break_mode(LineBreaking::Overflow)
@@ -18,6 +18,7 @@ use druid::{
 };
 use druid_widget_nursery::selectors;
 use notify::{RecursiveMode, Watcher};
+use tracing::warn;
 
 use crate::widgets::tree::node::TreeNode;
 use crate::widgets::tree::root::TreeNodeRoot;
@@ -446,7 +447,7 @@ fn msg_thread(rx: Receiver<Msg>, widget_id: WidgetId, sink: ExtEventSink) {
                     }
                 }
                 Err(err) => {
-                    dbg!(err);
+                    warn!(error = %err, "File watcher error");
                 }
             },
             i if i == timer_thread => {
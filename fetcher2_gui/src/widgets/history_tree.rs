@@ -2,6 +2,7 @@ use std::fmt::{Display, Formatter};
 use std::path::PathBuf;
 use std::sync::Arc;
 
+use chrono::Local;
 use druid::im::Vector;
 use druid::widget::Label;
 use druid::{
@@ -10,6 +11,7 @@ use druid::{
 };
 
 use fetcher2::template::node_type::site::{MsgKind, TaskMsg};
+use fetcher2::template::RunId;
 
 use crate::data::AppData;
 use crate::widgets::tree::node::TreeNode;
@@ -26,6 +28,8 @@ pub enum Type {
     ForbiddenExtension(Option<String>),
 
     InnerReplaced,
+
+    Run,
 }
 
 impl Display for Type {
@@ -38,6 +42,7 @@ impl Display for Type {
             Self::AlreadyExist => "Cached Checksum didn't Change",
             Self::ForbiddenExtension(_) => "Extension is Forbidden",
             Self::InnerReplaced => "Old File",
+            Self::Run => "Run",
         };
         f.write_str(str)
     }
@@ -95,6 +100,25 @@ impl Entry {
         }
     }
 
+    // groups the entries of one run, entries are ordered newest first
+    fn run(entries: Vec<TaskMsg>) -> Self {
+        let name = match entries.iter().find_map(|msg| msg.time) {
+            Some(time) => time
+                .with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M")
+                .to_string(),
+            None => "Unknown Time".to_owned(),
+        };
+        Self {
+            expanded: false,
+            name,
+            full_path: PathBuf::new(),
+            parent_path: format!("{} Files", entries.len()),
+            ty: Type::Run,
+            children: entries.into_iter().map(Entry::new).collect(),
+        }
+    }
+
     fn inner_replaced(path: PathBuf, parent_path: String) -> Self {
         let name = path
             .file_name()
@@ -126,11 +150,19 @@ impl EntryRoot {
     }
 
     pub fn new(history: Vector<TaskMsg>) -> Self {
-        let children = history
-            .iter()
-            .rev()
+        let mut runs: Vec<(Option<RunId>, Vec<TaskMsg>)> = Vec::new();
+        for task_msg in history.iter().rev() {
+            match runs.last_mut() {
+                Some((run_id, entries)) if *run_id == task_msg.run_id => {
+                    entries.push(task_msg.clone())
+                }
+                _ => runs.push((task_msg.run_id, vec![task_msg.clone()])),
+            }
+        }
+        let children = runs
+            .into_iter()
             .take(100)
-            .map(|task_msg| Entry::new(task_msg.clone()))
+            .map(|(_, entries)| Entry::run(entries))
             .collect();
 
         Self {
@@ -169,7 +201,9 @@ impl History {
         .sizes([300., 300., 300.])
         .on_activate(|_ctx, root, _env, idx| {
             let node = root.node(idx);
-            open::that_in_background(&node.full_path);
+            if node.ty != Type::Run {
+                open::that_in_background(&node.full_path);
+            }
         });
         Self {
             tree: WidgetPod::new(tree),
@@ -11,6 +11,7 @@ use druid::{
     Point, UpdateCtx, Widget, WidgetPod,
 };
 use itertools::Itertools;
+use tracing::debug;
 
 use header::Header;
 
@@ -268,7 +269,7 @@ where
         self.root_node.lifecycle(ctx, event, data, env);
         self.header.lifecycle(ctx, event, data, env);
         if t.elapsed() > Duration::from_millis(10) {
-            dbg!("LifeCycle", t.elapsed(), event);
+            debug!(elapsed = ?t.elapsed(), ?event, "Slow lifecycle");
         }
     }
 
@@ -277,7 +278,7 @@ where
         self.header.update(ctx, data, env);
         self.root_node.update(ctx, data, env);
         if t.elapsed() > Duration::from_millis(10) {
-            dbg!("Update", t.elapsed());
+            debug!(elapsed = ?t.elapsed(), "Slow update");
         }
         // TODO test if really needed
         // let lens_selected = self.selected_lens.get(data);
@@ -10,6 +10,7 @@ use druid::{
     Point, Selector, UpdateCtx, Widget, WidgetPod,
 };
 use druid_widget_nursery::selectors;
+use tracing::warn;
 
 pub use fetcher2_gui_derive::TreeNode;
 
@@ -295,7 +296,7 @@ impl<T: TreeNode, L: Lens<T, bool> + Clone, const N: usize> Widget<T> for TreeNo
 
         // don't go further with unhandled notifications
         if let Event::Notification(_) = event {
-            println!("SHould this ever happen?");
+            warn!(?event, "Unhandled notification");
             return;
         }
 
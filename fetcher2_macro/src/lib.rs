@@ -1,4 +1,5 @@
 use proc_macro::TokenStream;
+use std::str::FromStr;
 use std::sync::Mutex;
 
 use convert_case::{Case, Casing};
@@ -6,9 +7,15 @@ use once_cell::sync::Lazy;
 use proc_macro2::{Ident, Span};
 use quote::quote;
 use syn::parse::Parser;
-use syn::{self, parse_macro_input, DeriveInput, Field, Fields, Item};
+use syn::{self, parse_macro_input, Attribute, DeriveInput, Field, Fields, Item};
 
-static ENUM_DEFS: Lazy<Mutex<Vec<String>>> = Lazy::new(|| Mutex::new(Vec::new()));
+// the variants of Module with their cfg attributes, which the lock and its match arm keep
+static ENUM_DEFS: Lazy<Mutex<Vec<(String, String)>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+fn cfg_attrs(attrs: &[Attribute]) -> String {
+    let cfgs = attrs.iter().filter(|attr| attr.path.is_ident("cfg"));
+    quote! { #(#cfgs)* }.to_string()
+}
 
 #[proc_macro_attribute]
 pub fn login_locks(_attr: TokenStream, item: TokenStream) -> TokenStream {
@@ -17,7 +24,7 @@ pub fn login_locks(_attr: TokenStream, item: TokenStream) -> TokenStream {
         Item::Enum(item_enum) => {
             let mut enum_vec = ENUM_DEFS.lock().unwrap();
             for variant in item_enum.variants {
-                enum_vec.push(variant.ident.to_string());
+                enum_vec.push((variant.ident.to_string(), cfg_attrs(&variant.attrs)));
             }
             item.into()
         }
@@ -25,11 +32,12 @@ pub fn login_locks(_attr: TokenStream, item: TokenStream) -> TokenStream {
             match &mut item_struct.fields {
                 Fields::Named(fields) => {
                     let lock = ENUM_DEFS.lock().unwrap();
-                    for x in lock.iter() {
+                    for (x, cfgs) in lock.iter() {
                         let lock_name = Ident::new(&x.to_case(Case::Snake), Span::call_site());
+                        let cfgs = proc_macro2::TokenStream::from_str(cfgs).unwrap();
                         fields.named.push(
                             Field::parse_named
-                                .parse2(quote! { pub #lock_name: Mutex<LoginState> })
+                                .parse2(quote! { #cfgs pub #lock_name: Mutex<LoginState> })
                                 .unwrap(),
                         );
                     }
@@ -54,10 +62,11 @@ pub fn derive_login_lock(input: TokenStream) -> TokenStream {
         .lock()
         .unwrap()
         .iter()
-        .map(|x| {
+        .map(|(x, cfgs)| {
             let lock_name = Ident::new(&x.to_case(Case::Snake), Span::call_site());
             let name = Ident::new(&x, Span::call_site());
-            quote! { Module::#name(_) => locks.#lock_name.lock().await }
+            let cfgs = proc_macro2::TokenStream::from_str(cfgs).unwrap();
+            quote! { #cfgs Module::#name(_) => locks.#lock_name.lock().await }
         })
         .collect();
 